@@ -0,0 +1,6 @@
+//! The C interface of `src/ffi.rs` as a library with a name C programs can link against.
+//!
+//! Built with `cargo build --release` in this folder, see `../include/image_fingerprint.h` for the
+//! functions it exports.
+
+pub use lib::ffi::*;
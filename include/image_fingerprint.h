@@ -0,0 +1,113 @@
+#ifndef IMAGE_FINGERPRINT_H
+#define IMAGE_FINGERPRINT_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Hashes and the paths of the images they came from, see ifp_store_new and ifp_store_open.
+typedef struct HashStore HashStore;
+
+// The result of `ifp_store_search`, closest match first.
+typedef struct MatchList MatchList;
+
+// Hashes an 8 bit RGBA buffer into `out`, the same hash stores hold for the decoded image.
+//
+// Returns false if a pointer is null, the image is empty, `stride` is shorter than a row or
+// the buffer would be larger than memory can hold.
+//
+// # Safety
+//
+// `pixels` must point to `height` rows of `stride` bytes and `out` must be writable.
+bool ifp_dhash_rgba8(const uint8_t *pixels,
+                     uint32_t width,
+                     uint32_t height,
+                     size_t stride,
+                     uint64_t *out);
+
+// Hashes an 8 bit grayscale buffer into `out`, see `ifp_dhash_rgba8`.
+//
+// # Safety
+//
+// `pixels` must point to `height` rows of `stride` bytes and `out` must be writable.
+bool ifp_dhash_luma8(const uint8_t *pixels,
+                     uint32_t width,
+                     uint32_t height,
+                     size_t stride,
+                     uint64_t *out);
+
+// Number of differing bits, against whichever of `hash2` or its inverse is closer.
+uint32_t ifp_distance(uint64_t hash1, uint64_t hash2);
+
+// An empty store that has no file yet.
+HashStore *ifp_store_new(void);
+
+// Opens a store file, or returns null if it cannot be read.
+//
+// # Safety
+//
+// `path` must be a NUL terminated string.
+HashStore *ifp_store_open(const char *path);
+
+// # Safety
+//
+// `store` must come from `ifp_store_new` or `ifp_store_open`, or be null.
+void ifp_store_free(HashStore *store);
+
+// # Safety
+//
+// `store` must be a live store.
+size_t ifp_store_len(const HashStore *store);
+
+// # Safety
+//
+// `store` must be a live store and `path` a NUL terminated string.
+bool ifp_store_add(HashStore *store, uint64_t hash, const char *path);
+
+// Writes the store to `path`, or back to its own file when `path` is null.
+//
+// # Safety
+//
+// `store` must be a live store and `path` a NUL terminated string or null.
+bool ifp_store_save(HashStore *store, const char *path);
+
+// The `size` entries closest to any of the `count` hashes, closest first.
+//
+// Entries whose path contains a NUL byte are left out, so the list can come up short.
+// # Safety
+//
+// `store` must be a live store and `hashes` must point to `count` hashes.
+struct MatchList *ifp_store_search(const HashStore *store,
+                                   const uint64_t *hashes,
+                                   size_t count,
+                                   size_t size);
+
+// # Safety
+//
+// `matches` must come from `ifp_store_search`.
+size_t ifp_matches_len(const struct MatchList *matches);
+
+// Path of the match at `index`, valid until the list is freed. Null when out of range.
+//
+// # Safety
+//
+// `matches` must come from `ifp_store_search`.
+const char *ifp_matches_path(const struct MatchList *matches, size_t index);
+
+// Distance of the match at `index`, or `UINT32_MAX` when out of range.
+//
+// # Safety
+//
+// `matches` must come from `ifp_store_search`.
+uint32_t ifp_matches_distance(const struct MatchList *matches, size_t index);
+
+// # Safety
+//
+// `matches` must come from `ifp_store_search`, or be null.
+void ifp_matches_free(struct MatchList *matches);
+
+#endif  /* IMAGE_FINGERPRINT_H */
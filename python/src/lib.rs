@@ -0,0 +1,166 @@
+//! Python bindings for the hashes and stores of the `lib` crate.
+//!
+//! Build and install into the current environment with `maturin develop` from this directory.
+//! Hashes are plain ints holding the same bits as `IHash`, so they can be compared with what
+//! the GUI and CLI store.
+
+use std::path::PathBuf;
+
+use image::{imageops::FilterType, DynamicImage, RgbaImage};
+use pyo3::buffer::PyBuffer;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use lib::fgs;
+use lib::ihash::{self, IHash, PixelFormat};
+
+// PIL images are converted to RGBA. Anything else has to expose a buffer of bytes shaped
+// (height, width) or (height, width, channels) with 1, 3 or 4 channels, like a uint8 numpy array.
+fn to_image(py: Python<'_>, image: &Bound<'_, PyAny>) -> PyResult<DynamicImage> {
+    if image.hasattr("convert")? && image.hasattr("tobytes")? {
+        let rgba = image.call_method1("convert", ("RGBA",))?;
+        let (width, height): (u32, u32) = rgba.getattr("size")?.extract()?;
+        let pixels: Vec<u8> = rgba.call_method0("tobytes")?.extract()?;
+        return RgbaImage::from_raw(width, height, pixels)
+            .map(DynamicImage::ImageRgba8)
+            .ok_or_else(|| PyValueError::new_err("image data does not match its size"));
+    }
+
+    let buffer = PyBuffer::<u8>::get(image)?;
+    let shape = buffer.shape().to_vec();
+    let (height, width, channels) = match shape[..] {
+        [height, width] => (height as u32, width as u32, 1),
+        [height, width, channels] => (height as u32, width as u32, channels),
+        // Rejected below along with unknown channel counts
+        _ => (0, 0, 0),
+    };
+    // Copies in C order, so sliced and transposed arrays work as well
+    let pixels = buffer.to_vec(py)?;
+    let format = match channels {
+        1 => Some(PixelFormat::Luma8),
+        3 => Some(PixelFormat::Rgb8),
+        4 => Some(PixelFormat::Rgba8),
+        _ => None,
+    };
+    let image = format.and_then(|format| {
+        let stride = width as usize * format.bytes_per_pixel();
+        ihash::image_from_raw(&pixels, width, height, stride, format).ok()
+    });
+    image.ok_or_else(|| PyValueError::new_err(format!("unsupported shape {:?}", shape)))
+}
+
+/// dhash(image) -> int
+///
+/// The hash stores are built from, of a PIL image or a uint8 array.
+#[pyfunction]
+fn dhash(py: Python<'_>, image: &Bound<'_, PyAny>) -> PyResult<u64> {
+    Ok(ihash::dhash(&to_image(py, image)?).value())
+}
+
+/// dhash_rotations(image) -> list[int]
+///
+/// Hashes of the image turned by 0, 90, 180 and 270 degrees, for searching rotated copies.
+#[pyfunction]
+fn dhash_rotations(py: Python<'_>, image: &Bound<'_, PyAny>) -> PyResult<Vec<u64>> {
+    let hashes = ihash::dhash_rotations(&to_image(py, image)?, FilterType::Triangle);
+    Ok(hashes.into_iter().map(IHash::value).collect())
+}
+
+/// dhash_file(path) -> int
+///
+/// Decodes the file the same way the GUI does when fingerprinting a directory.
+#[pyfunction]
+fn dhash_file(path: PathBuf) -> PyResult<u64> {
+    lib::pipeline::hash_file(&path)
+        .map(IHash::value)
+        .ok_or_else(|| PyValueError::new_err(format!("could not decode {}", path.display())))
+}
+
+/// distance(hash1, hash2) -> int
+///
+/// Number of differing bits, against whichever of hash2 or its mirror is closer.
+#[pyfunction]
+fn distance(hash1: u64, hash2: u64) -> u32 {
+    IHash::new(hash1).dist(&IHash::new(hash2))
+}
+
+/// Hashes and the paths of the images they came from, read from the GUI's fingerprint files.
+#[pyclass]
+struct HashStore(fgs::HashStore);
+
+#[pymethods]
+impl HashStore {
+    #[new]
+    fn new() -> Self {
+        HashStore(fgs::HashStore::new())
+    }
+
+    #[staticmethod]
+    fn open(path: PathBuf) -> PyResult<Self> {
+        Ok(HashStore(fgs::HashStore::from_file(path)?))
+    }
+
+    /// The file the store was opened from or last saved to.
+    #[getter]
+    fn path(&self) -> Option<PathBuf> {
+        self.0.path().map(PathBuf::from)
+    }
+
+    fn __len__(&self) -> usize {
+        self.0.len()
+    }
+
+    fn entries(&self) -> Vec<(u64, PathBuf)> {
+        self.0
+            .iter()
+            .map(|(hash, path)| (hash.value(), path.to_path_buf()))
+            .collect()
+    }
+
+    fn add(&mut self, hash: u64, path: PathBuf) {
+        self.0.add_hash(&IHash::new(hash), path);
+    }
+
+    /// Drops every entry for the path and returns how many there were.
+    fn remove(&mut self, path: PathBuf) -> usize {
+        self.0.remove_path(path).len()
+    }
+
+    /// Writes to `path`, or back to the store's own file when it is left out.
+    #[pyo3(signature = (path=None))]
+    fn save(&mut self, path: Option<PathBuf>) -> PyResult<()> {
+        match path {
+            Some(path) => self.0.save_as(path)?,
+            None => self.0.save()?,
+        }
+        Ok(())
+    }
+
+    /// search(hashes, top=5) -> list[tuple[str, int]]
+    ///
+    /// The `top` closest paths to a hash or a list of hashes, with their distances.
+    #[pyo3(signature = (hashes, top=5))]
+    fn search(&self, hashes: &Bound<'_, PyAny>, top: usize) -> PyResult<Vec<(PathBuf, u32)>> {
+        let hashes: Vec<u64> = match hashes.extract::<u64>() {
+            Ok(hash) => vec![hash],
+            Err(_) => hashes.extract()?,
+        };
+        let hashes: Vec<IHash> = hashes.into_iter().map(IHash::new).collect();
+        Ok(self
+            .0
+            .find_matches(&hashes, top)
+            .into_iter()
+            .map(|m| (m.path, m.distance))
+            .collect())
+    }
+}
+
+#[pymodule]
+fn image_fingerprint(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(dhash, module)?)?;
+    module.add_function(wrap_pyfunction!(dhash_rotations, module)?)?;
+    module.add_function(wrap_pyfunction!(dhash_file, module)?)?;
+    module.add_function(wrap_pyfunction!(distance, module)?)?;
+    module.add_class::<HashStore>()?;
+    Ok(())
+}
@@ -0,0 +1,105 @@
+import tempfile
+import unittest
+from pathlib import Path
+
+import image_fingerprint as fp
+
+POKEMON = Path(__file__).resolve().parents[2] / "test" / "pokemon"
+
+try:
+    import numpy
+except ImportError:
+    numpy = None
+
+try:
+    from PIL import Image
+except ImportError:
+    Image = None
+
+
+def gradient(width, height, channels):
+    """Brightness rising to the right, so every dhash bit is set."""
+    pixels = bytearray()
+    for _ in range(height):
+        for x in range(width):
+            pixels += bytes([x * 20] * channels)
+    shape = [height, width] if channels == 1 else [height, width, channels]
+    return memoryview(bytes(pixels)).cast("B", shape)
+
+
+def pattern(width, height):
+    """Grayscale rows with no symmetry, so each rotation hashes differently."""
+    return [[(x * 7 + y * 13 + x * y * 5) % 256 for x in range(width)] for y in range(height)]
+
+
+def rotate(rows):
+    """The rows turned by 90 degrees clockwise."""
+    return [list(row) for row in zip(*rows[::-1])]
+
+
+def luma(rows):
+    pixels = bytes(value for row in rows for value in row)
+    return memoryview(pixels).cast("B", [len(rows), len(rows[0])])
+
+
+class HashTest(unittest.TestCase):
+    def test_buffer_shapes(self):
+        for channels in (1, 3, 4):
+            self.assertEqual(fp.dhash(gradient(9, 8, channels)), 2**64 - 1)
+        with self.assertRaises(ValueError):
+            fp.dhash(gradient(9, 8, 2))
+
+    def test_rotations(self):
+        rows = pattern(32, 24)
+        hashes = fp.dhash_rotations(luma(rows))
+        self.assertEqual(len(hashes), 4)
+        self.assertEqual(len(set(hashes)), 4)
+        self.assertEqual(hashes[0], fp.dhash(luma(rows)))
+
+        # A rotated copy finds the original through one of its rotations
+        stored = fp.dhash(luma(rows))
+        rotated = fp.dhash_rotations(luma(rotate(rows)))
+        self.assertLessEqual(min(fp.distance(h, stored) for h in rotated), 2)
+
+    def test_distance(self):
+        self.assertEqual(fp.distance(1, 3), 1)
+        # The mirrored hash counts as a match
+        self.assertEqual(fp.distance(0, 2**64 - 1), 0)
+
+    @unittest.skipUnless(numpy and Image, "needs numpy and pillow")
+    def test_arrays_and_images_match_files(self):
+        path = POKEMON / "bulbasaur.png"
+        image = Image.open(path)
+        self.assertEqual(fp.dhash(image), fp.dhash_file(str(path)))
+        self.assertEqual(fp.dhash(numpy.asarray(image.convert("RGBA"))), fp.dhash_file(str(path)))
+
+
+class HashStoreTest(unittest.TestCase):
+    def test_search_round_trip(self):
+        store = fp.HashStore()
+        for path in sorted(POKEMON.glob("*.png")):
+            store.add(fp.dhash_file(str(path)), str(path))
+        self.assertEqual(len(store), 4)
+
+        with tempfile.TemporaryDirectory() as tmp:
+            file = Path(tmp) / "store.json"
+            store.save(str(file))
+            store = fp.HashStore.open(str(file))
+            self.assertEqual(Path(store.path), file)
+
+        query = POKEMON / "ivysaur.png"
+        matches = store.search(fp.dhash_file(str(query)), top=2)
+        self.assertEqual(len(matches), 2)
+        self.assertEqual((Path(matches[0][0]), matches[0][1]), (query, 0))
+        self.assertEqual(store.search([1, 2], top=10)[0][1], store.search(1)[0][1])
+
+        self.assertEqual(store.remove(str(query)), 1)
+        self.assertEqual(len(store.entries()), 3)
+
+    def test_open_missing(self):
+        with self.assertRaises(FileNotFoundError):
+            fp.HashStore.open("/no/such/store.json")
+
+
+if __name__ == "__main__":
+    unittest.main()
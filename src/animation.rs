@@ -0,0 +1,187 @@
+//! Every frame of animated GIF and PNG (APNG) files.
+//!
+//! Frames are stored under the path of the file followed by `#frame=` and their index, counting
+//! from 0, like `reactions/wave.gif#frame=12`. Files with a single frame keep their plain path.
+//! Animated WebP files cannot be decoded by the image crate yet and are skipped.
+
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use image::codecs::{gif::GifDecoder, png::PngDecoder};
+use image::{AnimationDecoder, DynamicImage, Frames, ImageResult};
+
+use crate::ihash::PixelFormat;
+use crate::pipeline::{split_around, HashSource};
+
+const SEPARATOR: &str = "#frame=";
+
+/// Which frames of an animation end up in the store.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FrameSampling {
+    /// Only the first frame, stored under the plain path like any other image
+    #[default]
+    First,
+    All,
+    /// The first frame and every n-th one after it
+    Every(usize),
+}
+
+impl FrameSampling {
+    fn keeps(self, index: usize) -> bool {
+        match self {
+            FrameSampling::First => index == 0,
+            FrameSampling::All => true,
+            FrameSampling::Every(step) => index.is_multiple_of(step.max(1)),
+        }
+    }
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
+
+/// Whether the file is of a type that can hold an animation, not whether it does.
+pub fn is_animated(path: &Path) -> bool {
+    has_extension(path, "gif") || has_extension(path, "png")
+}
+
+/// The path frame `index` of the animation is stored under.
+pub fn frame_path(path: &Path, index: usize) -> PathBuf {
+    let mut frame = OsString::from(path);
+    frame.push(format!("{}{}", SEPARATOR, index));
+    frame.into()
+}
+
+/// Splits a path made by [`frame_path`] back into the file and the index of the frame.
+pub fn split_frame(path: &Path) -> Option<(&Path, usize)> {
+    let (file, index) = split_around(path, SEPARATOR).next_back()?;
+    let index = index.to_str()?.parse().ok()?;
+    is_animated(file).then_some((file, index))
+}
+
+// None for PNGs without an animation
+fn frames(path: &Path) -> ImageResult<Option<Frames<'static>>> {
+    let file = BufReader::new(File::open(path)?);
+    if has_extension(path, "gif") {
+        return Ok(Some(GifDecoder::new(file)?.into_frames()));
+    }
+    let png = PngDecoder::new(file)?;
+    Ok(png.is_apng().then(|| png.apng().into_frames()))
+}
+
+/// Hands the frames picked by `sampling` to `f` as the decoder produces them, with the path
+/// each is stored under, until `f` returns false.
+///
+/// Images with a single frame are handed over as one [`HashSource::File`] under their plain path.
+pub fn for_each_source<F>(path: &Path, sampling: FrameSampling, mut f: F) -> ImageResult<()>
+where
+    F: FnMut(PathBuf, HashSource) -> bool,
+{
+    let plain = || HashSource::File(path.to_path_buf());
+    let frames = match frames(path)? {
+        Some(frames) if sampling != FrameSampling::First => frames,
+        _ => {
+            f(path.to_path_buf(), plain());
+            return Ok(());
+        }
+    };
+    // Held back until a second frame shows whether this is an animation at all
+    let mut first = None;
+    for (index, frame) in frames.enumerate() {
+        let frame = frame?;
+        if let Some(source) = first.take() {
+            if !f(frame_path(path, 0), source) {
+                return Ok(());
+            }
+        }
+        if !sampling.keeps(index) {
+            continue;
+        }
+        let buffer = frame.into_buffer();
+        let (width, height) = buffer.dimensions();
+        let source = HashSource::Raw {
+            pixels: buffer.into_raw(),
+            width,
+            height,
+            stride: width as usize * PixelFormat::Rgba8.bytes_per_pixel(),
+            format: PixelFormat::Rgba8,
+        };
+        if index == 0 {
+            first = Some(source);
+        } else if !f(frame_path(path, index), source) {
+            return Ok(());
+        }
+    }
+    if first.is_some() {
+        f(path.to_path_buf(), plain());
+    }
+    Ok(())
+}
+
+/// Decodes the single frame a path made by [`frame_path`] points to.
+pub fn read_frame(path: &Path) -> ImageResult<DynamicImage> {
+    let (file, index) = split_frame(path)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "not an animation frame"))?;
+    let frame = frames(file)?
+        .and_then(|mut frames| frames.nth(index))
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "no such frame"))?;
+    Ok(DynamicImage::ImageRgba8(frame?.into_buffer()))
+}
+
+#[test]
+fn hash_animation_frames() {
+    use crate::pipeline::{hash_file, hash_files_with};
+    use image::{codecs::gif::GifEncoder, imageops::FilterType, Frame};
+
+    let gif = std::env::temp_dir().join("image-fingerprint-animation.gif");
+    let pokemon = ["bulbasaur", "ivysaur", "bulbasaur"].map(|name| {
+        image::open(format!("./test/pokemon/{}.png", name))
+            .unwrap()
+            .resize_exact(64, 64, FilterType::Triangle)
+            .to_rgba8()
+    });
+    let mut encoder = GifEncoder::new(File::create(&gif).unwrap());
+    encoder
+        .encode_frames(pokemon.iter().map(|image| Frame::new(image.clone())))
+        .unwrap();
+    drop(encoder);
+
+    let hashed: Vec<_> = hash_files_with(vec![gif.clone()], FrameSampling::All)
+        .iter()
+        .collect();
+    let paths: Vec<PathBuf> = hashed.iter().map(|(_, path)| path.clone()).collect();
+    let expected: Vec<PathBuf> = (0..3).map(|i| frame_path(&gif, i)).collect();
+    assert_eq!(paths, expected);
+    assert_eq!(hashed[0].0, hashed[2].0);
+    assert_ne!(hashed[0].0, hashed[1].0);
+    for (hash, path) in hashed.iter() {
+        assert_eq!(hash_file(path), Some(*hash));
+    }
+    assert_eq!(split_frame(&paths[1]), Some((gif.as_path(), 1)));
+
+    let every_other: Vec<_> = hash_files_with(vec![gif.clone()], FrameSampling::Every(2))
+        .iter()
+        .map(|(_, path)| path)
+        .collect();
+    assert_eq!(every_other, [frame_path(&gif, 0), frame_path(&gif, 2)]);
+    let first: Vec<_> = hash_files_with(vec![gif.clone()], FrameSampling::First)
+        .iter()
+        .collect();
+    assert_eq!(first, [(hashed[0].0, gif.clone())]);
+    assert!(read_frame(&frame_path(&gif, 3)).is_err());
+
+    // Still PNGs are not animations
+    let still = PathBuf::from("./test/pokemon/ivysaur.png");
+    let mut paths = vec![];
+    for_each_source(&still, FrameSampling::All, |path, _| {
+        paths.push(path);
+        true
+    })
+    .unwrap();
+    assert_eq!(paths, [still]);
+    assert_eq!(split_frame(Path::new("notes.txt#frame=1")), None);
+    let _ = std::fs::remove_file(&gif);
+}
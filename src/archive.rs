@@ -0,0 +1,185 @@
+//! Images stored inside ZIP, comic book (CBZ) and TAR files.
+//!
+//! Members are stored under the path of the archive followed by `!/` and their name inside it,
+//! like `comics/issue1.cbz!/pages/001.png`, and read back out of the archive whenever that path
+//! has to be decoded again.
+
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+
+use crate::pipeline::{is_image, split_around, HashSource};
+
+pub const ARCHIVE_EXTENSIONS: &[&str] = &["zip", "cbz", "tar"];
+
+const SEPARATOR: &str = "!/";
+
+pub fn is_archive(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        ARCHIVE_EXTENSIONS
+            .iter()
+            .any(|known| ext.eq_ignore_ascii_case(known))
+    })
+}
+
+fn is_tar(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("tar"))
+}
+
+/// The path a member of the archive is stored under.
+pub fn member_path(archive: &Path, member: &str) -> PathBuf {
+    let mut path = OsString::from(archive);
+    path.push(SEPARATOR);
+    path.push(member);
+    path.into()
+}
+
+/// Splits a path made by [`member_path`] back into the archive and the member name.
+///
+/// None for anything else, including plain files that merely contain a `!/`.
+pub fn split_member(path: &Path) -> Option<(&Path, &str)> {
+    split_around(path, SEPARATOR)
+        .filter_map(|(archive, member)| Some((archive, member.to_str()?)))
+        .find(|(archive, member)| is_archive(archive) && !member.is_empty())
+}
+
+/// Reads the members that look like an image, by extension, one at a time in the order of the
+/// archive, handing each to `f` until it returns false.
+pub fn for_each_image<F>(archive: &Path, mut f: F) -> Result<(), Error>
+where
+    F: FnMut(String, Vec<u8>) -> bool,
+{
+    let file = BufReader::new(File::open(archive)?);
+    if is_tar(archive) {
+        for entry in tar::Archive::new(file).entries()? {
+            let mut entry = entry?;
+            let name = entry.path()?.to_string_lossy().into_owned();
+            if entry.header().entry_type().is_file() && is_image(Path::new(&name)) {
+                let mut bytes = vec![];
+                entry.read_to_end(&mut bytes)?;
+                if !f(name, bytes) {
+                    break;
+                }
+            }
+        }
+    } else {
+        let mut zip = zip::ZipArchive::new(file)?;
+        for i in 0..zip.len() {
+            let mut entry = zip.by_index(i)?;
+            if entry.is_file() && is_image(Path::new(entry.name())) {
+                let mut bytes = vec![];
+                entry.read_to_end(&mut bytes)?;
+                if !f(entry.name().to_string(), bytes) {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads every member that looks like an image at once, see [`for_each_image`].
+pub fn image_members(archive: &Path) -> Result<Vec<(String, Vec<u8>)>, Error> {
+    let mut members = vec![];
+    for_each_image(archive, |name, bytes| {
+        members.push((name, bytes));
+        true
+    })?;
+    Ok(members)
+}
+
+/// The contents of the member a path made by [`member_path`] points to.
+pub fn read_member(path: &Path) -> Result<Vec<u8>, Error> {
+    let (archive, member) = split_member(path)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "not an archive member"))?;
+    let file = BufReader::new(File::open(archive)?);
+    let mut bytes = vec![];
+    if is_tar(archive) {
+        for entry in tar::Archive::new(file).entries()? {
+            let mut entry = entry?;
+            if entry.path()?.to_string_lossy() == member {
+                entry.read_to_end(&mut bytes)?;
+                return Ok(bytes);
+            }
+        }
+        Err(Error::new(ErrorKind::NotFound, "no such member"))
+    } else {
+        let mut zip = zip::ZipArchive::new(file)?;
+        zip.by_name(member)?.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+/// Hands every image in the archive to `f` with the path it is stored under, one at a time
+/// until `f` returns false.
+pub fn for_each_source<F>(archive: &Path, mut f: F) -> Result<(), Error>
+where
+    F: FnMut(PathBuf, HashSource) -> bool,
+{
+    for_each_image(archive, |name, bytes| {
+        f(member_path(archive, &name), HashSource::Encoded(bytes))
+    })
+}
+
+#[test]
+fn archive_members() {
+    use std::io::Write;
+
+    let dir = std::env::temp_dir().join("image-fingerprint-archive");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let png = std::fs::read("./test/pokemon/bulbasaur.png").unwrap();
+
+    let cbz = dir.join("issue1.cbz");
+    let mut writer = zip::ZipWriter::new(File::create(&cbz).unwrap());
+    let options = zip::write::FileOptions::default();
+    writer.add_directory("pages/", options).unwrap();
+    writer.start_file("pages/001.png", options).unwrap();
+    writer.write_all(&png).unwrap();
+    writer.start_file("ComicInfo.xml", options).unwrap();
+    writer.write_all(b"<ComicInfo/>").unwrap();
+    writer.finish().unwrap();
+
+    let tar_path = dir.join("photos.tar");
+    let mut builder = tar::Builder::new(File::create(&tar_path).unwrap());
+    let mut header = tar::Header::new_gnu();
+    header.set_size(png.len() as u64);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, "2020/bulbasaur.png", &png[..])
+        .unwrap();
+    builder.finish().unwrap();
+    drop(builder);
+
+    for (archive, name) in [(&cbz, "pages/001.png"), (&tar_path, "2020/bulbasaur.png")] {
+        let members = image_members(archive).unwrap();
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0], (name.to_string(), png.clone()));
+
+        let path = member_path(archive, name);
+        assert_eq!(split_member(&path), Some((archive.as_path(), name)));
+        assert_eq!(crate::pipeline::on_disk(&path), archive.as_path());
+        assert_eq!(read_member(&path).unwrap(), png);
+        let missing = member_path(archive, "nope.png");
+        assert!(read_member(&missing).is_err());
+    }
+    let hashed: Vec<_> = crate::pipeline::hash_files(vec![cbz.clone(), tar_path.clone()])
+        .iter()
+        .collect();
+    let paths: Vec<_> = hashed.iter().map(|(_, path)| path.clone()).collect();
+    assert_eq!(
+        paths,
+        [
+            member_path(&cbz, "pages/001.png"),
+            member_path(&tar_path, "2020/bulbasaur.png")
+        ]
+    );
+    assert_eq!(crate::pipeline::hash_file(&paths[0]), Some(hashed[0].0));
+
+    assert_eq!(split_member(Path::new("wow!/such.png")), None);
+    assert_eq!(split_member(&dir.join("a.zip!/")), None);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,127 @@
+use std::fs::{read_dir, File};
+use std::io::{BufWriter, Error, Write};
+use std::path::{Path, PathBuf};
+
+use image::imageops::FilterType;
+use serde::Serialize;
+
+use crate::fgs::{HashStore, Match};
+use crate::ihash::dhash_rotations;
+
+/// The closest stored images for a single query image.
+#[derive(Clone, Debug, Serialize)]
+pub struct QueryResult {
+    #[serde(with = "crate::paths")]
+    pub query: PathBuf,
+    pub matches: Vec<Match>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct BatchReport {
+    pub results: Vec<QueryResult>,
+}
+
+/// Searches the store for every image in `paths`, keeping the `top_n` closest matches of each.
+///
+/// Files that cannot be decoded as images are skipped.
+pub fn query_paths(store: &HashStore, paths: &[PathBuf], top_n: usize) -> BatchReport {
+    let mut report = BatchReport::default();
+    for path in paths {
+        if let Ok(image) = image::open(path) {
+            let hashes = dhash_rotations(&image, FilterType::Triangle);
+            report.results.push(QueryResult {
+                query: path.clone(),
+                matches: store.find_matches(&hashes, top_n),
+            });
+        }
+    }
+    report
+}
+
+/// Runs [`query_paths`] over the files directly inside `dir`, in name order.
+pub fn query_dir(store: &HashStore, dir: &Path, top_n: usize) -> Result<BatchReport, Error> {
+    let mut paths: Vec<PathBuf> = vec![];
+    for entry in read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            paths.push(entry.path());
+        }
+    }
+    paths.sort();
+    Ok(query_paths(store, &paths, top_n))
+}
+
+impl BatchReport {
+    pub fn to_json<W: Write>(&self, writer: W) -> Result<(), Error> {
+        serde_json::to_writer_pretty(writer, &self.results)?;
+        Ok(())
+    }
+
+    /// One row per match, queries without any match get a single row with empty match columns.
+    pub fn to_csv<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        writeln!(writer, "query,rank,match,distance")?;
+        for result in self.results.iter() {
+            let query = csv_field(&result.query.to_string_lossy());
+            if result.matches.is_empty() {
+                writeln!(writer, "{},,,", query)?;
+            }
+            for (rank, m) in result.matches.iter().enumerate() {
+                writeln!(
+                    writer,
+                    "{},{},{},{}",
+                    query,
+                    rank + 1,
+                    csv_field(&m.path.to_string_lossy()),
+                    m.distance
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes CSV when the path ends in `.csv` and JSON otherwise.
+    pub fn to_file(&self, path: &Path) -> Result<(), Error> {
+        let writer = BufWriter::new(File::create(path)?);
+        let is_csv = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+        if is_csv {
+            self.to_csv(writer)
+        } else {
+            self.to_json(writer)
+        }
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[test]
+fn batch_query_finds_itself() {
+    use crate::ihash::dhash;
+
+    let dir = Path::new("./test/pokemon");
+    let mut store = HashStore::new();
+    for entry in read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        store.add_hash(&dhash(&image::open(&path).unwrap()), path.to_str().unwrap());
+    }
+
+    let report = query_dir(&store, dir, 2).unwrap();
+    assert_eq!(report.results.len(), 4);
+    for result in report.results.iter() {
+        assert_eq!(result.matches.len(), 2);
+        assert_eq!(result.matches[0].distance, 0);
+    }
+
+    let mut csv: Vec<u8> = vec![];
+    report.to_csv(&mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    assert_eq!(csv.lines().count(), 1 + 4 * 2);
+    assert!(csv.starts_with("query,rank,match,distance\n"));
+}
@@ -0,0 +1,304 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use lib::{
+    batch,
+    fgs::HashStore,
+    merge::ConflictPolicy,
+    video::{self, Sampling},
+};
+
+const USAGE: &str = "usage:
+    fingerprint-cli batch <store.json> <query-dir> <report.csv|report.json> [--top N]
+    fingerprint-cli stats <store.json> [--verify]
+    fingerprint-cli merge <ours.json> <theirs.json> <out.json> [--keep ours|theirs|both]
+    fingerprint-cli diff <ours.json> <theirs.json>
+    fingerprint-cli split <store.json> <prefix> <inside.json> <outside.json>
+    fingerprint-cli roots <store.json> [add <name> <dir> | remove <name> | relocate <name> <dir>]
+    fingerprint-cli videos <store.json> <video>... [--every SECONDS | --scenes BITS]
+    fingerprint-cli serve <store.json> [--port N]";
+
+fn main() -> ExitCode {
+    // Paths need not be valid UTF-8, so arguments stay OsStrings until they are parsed
+    let mut args: Vec<OsString> = std::env::args_os().skip(1).collect();
+    let result = match args.first().and_then(|a| a.to_str()) {
+        Some("batch") => batch_query(&mut args.split_off(1)),
+        Some("stats") => stats(&mut args.split_off(1)),
+        Some("merge") => merge(&mut args.split_off(1)),
+        Some("diff") => diff(&args[1..]),
+        Some("split") => split(&args[1..]),
+        Some("roots") => roots(&args[1..]),
+        Some("videos") => videos(&mut args.split_off(1)),
+        #[cfg(feature = "server")]
+        Some("serve") => serve(&mut args.split_off(1)),
+        _ => Err(USAGE.to_string()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn batch_query(args: &mut Vec<OsString>) -> Result<(), String> {
+    let top = take_option(args, "--top")?.unwrap_or(5);
+    let [store, query_dir, report] = positional(args)?;
+
+    let store = open_store(&store)?;
+    let result = batch::query_dir(&store, &query_dir, top)
+        .map_err(|e| format!("{}: {}", query_dir.display(), e))?;
+    result
+        .to_file(&report)
+        .map_err(|e| format!("{}: {}", report.display(), e))?;
+    println!(
+        "{} queries written to {}",
+        result.results.len(),
+        report.display()
+    );
+    Ok(())
+}
+
+fn stats(args: &mut Vec<OsString>) -> Result<(), String> {
+    let verify = take_flag(args, "--verify");
+    let [path] = positional(args)?;
+    let store = open_store(&path)?;
+
+    let stats = store.stats();
+    println!(
+        "{} entries, {} unique paths",
+        stats.entries, stats.unique_paths
+    );
+    print_paths("paths stored more than once", &stats.duplicate_paths);
+    println!("distance to nearest neighbour:");
+    for (distance, count) in stats.nearest_neighbours.iter().enumerate() {
+        if *count > 0 {
+            println!("{:>4} {}", distance, count);
+        }
+    }
+
+    if verify {
+        let report = store.verify();
+        println!("checked {} files", report.checked);
+        print_paths("missing files", &report.missing);
+        print_paths("unreadable files", &report.unreadable);
+        for changed in report.changed.iter() {
+            println!(
+                "changed: {} (stored {}, now {})",
+                changed.path.display(),
+                changed.stored.to_str(),
+                changed.current.to_str()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn merge(args: &mut Vec<OsString>) -> Result<(), String> {
+    let policy = match take_option::<String>(args, "--keep")?.as_deref() {
+        None | Some("ours") => ConflictPolicy::KeepOurs,
+        Some("theirs") => ConflictPolicy::KeepTheirs,
+        Some("both") => ConflictPolicy::KeepBoth,
+        Some(other) => return Err(format!("invalid value for --keep: {}", other)),
+    };
+    let [ours, theirs, out] = positional(args)?;
+
+    let mut store = open_store(&ours)?;
+    let summary = store.merge(&open_store(&theirs)?, policy);
+    store
+        .to_file(&out)
+        .map_err(|e| format!("{}: {}", out.display(), e))?;
+    println!(
+        "added {} entries, {} conflicting paths",
+        summary.added, summary.conflicts
+    );
+    Ok(())
+}
+
+fn diff(args: &[OsString]) -> Result<(), String> {
+    let [ours, theirs] = positional(args)?;
+    let diff = open_store(&ours)?.diff(&open_store(&theirs)?);
+    for (hash, path) in diff.only_ours.iter() {
+        println!("< {} {}", hash.to_str(), path.display());
+    }
+    for (hash, path) in diff.only_theirs.iter() {
+        println!("> {} {}", hash.to_str(), path.display());
+    }
+    for changed in diff.changed.iter() {
+        let hashes = |hashes: &[lib::ihash::IHash]| {
+            hashes
+                .iter()
+                .map(|h| h.to_str())
+                .collect::<Vec<String>>()
+                .join(",")
+        };
+        println!(
+            "~ {} {} {}",
+            hashes(&changed.ours),
+            hashes(&changed.theirs),
+            changed.path.display()
+        );
+    }
+    Ok(())
+}
+
+fn split(args: &[OsString]) -> Result<(), String> {
+    let [path, prefix, inside_path, outside_path] = positional(args)?;
+    let (inside, outside) = open_store(&path)?.split(&prefix);
+    inside
+        .to_file(&inside_path)
+        .map_err(|e| format!("{}: {}", inside_path.display(), e))?;
+    outside
+        .to_file(&outside_path)
+        .map_err(|e| format!("{}: {}", outside_path.display(), e))?;
+    println!(
+        "{} entries under {}, {} elsewhere",
+        inside.len(),
+        prefix.display(),
+        outside.len()
+    );
+    Ok(())
+}
+
+fn roots(args: &[OsString]) -> Result<(), String> {
+    let (path, command) = args.split_first().ok_or_else(|| USAGE.to_string())?;
+    let path = Path::new(path);
+    let mut store = open_store(path)?;
+    // Root names are stored as text, their directories as paths
+    let text = |name: &OsString| {
+        name.to_str()
+            .map(str::to_string)
+            .ok_or_else(|| format!("root name is not valid UTF-8: {}", name.to_string_lossy()))
+    };
+    match command {
+        [] => {
+            for (name, root) in store.roots() {
+                println!("{} {}", name, root.display());
+            }
+            return Ok(());
+        }
+        [add, name, dir] if add == "add" => store.add_root(&text(name)?, Path::new(dir)),
+        [remove, name] if remove == "remove" => {
+            let name = text(name)?;
+            store
+                .remove_root(&name)
+                .ok_or_else(|| format!("no root named {}", name))?;
+        }
+        [relocate, name, dir] if relocate == "relocate" => {
+            let name = text(name)?;
+            let moved = store
+                .relocate_root(&name, Path::new(dir))
+                .ok_or_else(|| format!("no root named {}", name))?;
+            println!("{} entries moved to {}", moved, Path::new(dir).display());
+        }
+        _ => return Err(USAGE.to_string()),
+    }
+    store
+        .save()
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(())
+}
+
+// Frames are stored as <video>#t=<seconds>, sampling a video again replaces its old frames
+fn videos(args: &mut Vec<OsString>) -> Result<(), String> {
+    let every: Option<f64> = take_option(args, "--every")?;
+    let scenes: Option<u32> = take_option(args, "--scenes")?;
+    let sampling = match (every, scenes) {
+        (None, None) => Sampling::Interval(1.0),
+        (Some(seconds), None) if seconds > 0.0 => Sampling::Interval(seconds),
+        (None, Some(bits)) => Sampling::SceneChange(bits),
+        _ => return Err(USAGE.to_string()),
+    };
+    let (path, videos) = match args.split_first() {
+        Some((path, videos)) if !videos.is_empty() => (Path::new(path), videos),
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let mut store = if path.exists() {
+        open_store(path)?
+    } else {
+        HashStore::new()
+    };
+    for video in videos.iter().map(Path::new) {
+        let frames = video::hash_video(video, sampling)
+            .map_err(|e| format!("{}: {}", video.display(), e))?;
+        let stale: Vec<PathBuf> = store
+            .iter()
+            .filter(|(_, p)| video::split_frame(p).is_some_and(|(v, _)| v == video))
+            .map(|(_, p)| p.to_path_buf())
+            .collect();
+        for frame in stale {
+            store.remove_path(frame);
+        }
+        for (hash, frame) in frames.iter() {
+            store.add_hash(hash, frame);
+        }
+        println!("{}: {} frames", video.display(), frames.len());
+    }
+    store
+        .save_as(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(())
+}
+
+#[cfg(feature = "server")]
+fn serve(args: &mut Vec<OsString>) -> Result<(), String> {
+    use lib::server::Server;
+
+    let port = take_option(args, "--port")?.unwrap_or(8000);
+    let [path] = positional(args)?;
+    let mut server =
+        Server::bind(open_store(&path)?, port).map_err(|e| format!("port {}: {}", port, e))?;
+    println!(
+        "serving {} entries on http://{}",
+        server.store().len(),
+        server.addr()
+    );
+    server.run();
+    Ok(())
+}
+
+fn print_paths(title: &str, paths: &[PathBuf]) {
+    println!("{} {}", paths.len(), title);
+    for path in paths {
+        println!("    {}", path.display());
+    }
+}
+
+fn open_store(path: &Path) -> Result<HashStore, String> {
+    HashStore::from_file(path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn take_flag(args: &mut Vec<OsString>, name: &str) -> bool {
+    let before = args.len();
+    args.retain(|a| a != name);
+    args.len() != before
+}
+
+/// Removes `--name value` from the arguments and parses the value.
+fn take_option<T: std::str::FromStr>(
+    args: &mut Vec<OsString>,
+    name: &str,
+) -> Result<Option<T>, String> {
+    match args.iter().position(|a| a == name) {
+        Some(i) if i + 1 < args.len() => {
+            let value = args.remove(i + 1);
+            args.remove(i);
+            value
+                .to_str()
+                .and_then(|v| v.parse().ok())
+                .map(Some)
+                .ok_or_else(|| format!("invalid value for {}: {}", name, value.to_string_lossy()))
+        }
+        Some(_) => Err(format!("missing value for {}", name)),
+        None => Ok(None),
+    }
+}
+
+// Every positional argument is a path
+fn positional<const N: usize>(args: &[OsString]) -> Result<[PathBuf; N], String> {
+    let paths: Vec<PathBuf> = args.iter().map(PathBuf::from).collect();
+    <[PathBuf; N]>::try_from(paths).map_err(|_| USAGE.to_string())
+}
@@ -0,0 +1,294 @@
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::fgs::HashStore;
+use crate::ihash::IHash;
+
+const UNDO_LOG: &str = "undo.json";
+
+/// What decides which file of a duplicate group survives.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeepRule {
+    /// Most pixels, then the biggest file
+    Largest,
+    /// Most recently modified
+    Newest,
+    /// Anything under this folder, largest first
+    InFolder(PathBuf),
+}
+
+/// What a duplicate reviewer needs to know about a file.
+#[derive(Clone, Debug)]
+pub struct FileInfo {
+    pub path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl FileInfo {
+    pub fn read(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        let (width, height) = image::image_dimensions(path).ok()?;
+        Some(FileInfo {
+            path: path.to_path_buf(),
+            width,
+            height,
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+/// Index of the file to keep, or None when no file satisfies the rule.
+pub fn pick_keeper(files: &[FileInfo], rule: &KeepRule) -> Option<usize> {
+    let largest = |candidates: Vec<(usize, &FileInfo)>| {
+        candidates
+            .into_iter()
+            .max_by_key(|(_, f)| (f.width as u64 * f.height as u64, f.size))
+            .map(|(i, _)| i)
+    };
+    match rule {
+        KeepRule::Largest => largest(files.iter().enumerate().collect()),
+        KeepRule::Newest => files
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, f)| f.modified)
+            .map(|(i, _)| i),
+        KeepRule::InFolder(folder) => largest(
+            files
+                .iter()
+                .enumerate()
+                .filter(|(_, f)| f.path.starts_with(folder))
+                .collect(),
+        ),
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QuarantineEntry {
+    #[serde(with = "crate::paths")]
+    pub original: PathBuf,
+    #[serde(with = "crate::paths")]
+    pub quarantined: PathBuf,
+    pub hashes: Vec<IHash>,
+}
+
+/// A folder that duplicates are moved into instead of being deleted.
+///
+/// Every move is recorded in an undo log kept inside the folder, so it can be reverted later.
+pub struct Quarantine {
+    folder: PathBuf,
+    log: Vec<QuarantineEntry>,
+}
+
+impl Quarantine {
+    pub fn open(folder: &Path) -> Result<Self, Error> {
+        fs::create_dir_all(folder)?;
+        let log = match File::open(folder.join(UNDO_LOG)) {
+            Ok(file) => serde_json::from_reader(file)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+        Ok(Quarantine {
+            folder: folder.to_path_buf(),
+            log,
+        })
+    }
+
+    pub fn folder(&self) -> &Path {
+        &self.folder
+    }
+
+    pub fn log(&self) -> &[QuarantineEntry] {
+        &self.log
+    }
+
+    /// Moves the file into the quarantine folder and drops it from the store.
+    ///
+    /// The move is logged before it happens, so a file never ends up in quarantine without a
+    /// way back.
+    pub fn quarantine(&mut self, store: &mut HashStore, path: &Path) -> Result<(), Error> {
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Not a file path"))?;
+        let mut target = self.folder.join(file_name);
+        let mut n = 1;
+        while target.exists() {
+            let mut numbered = OsString::from(format!("{}_", n));
+            numbered.push(file_name);
+            target = self.folder.join(numbered);
+            n += 1;
+        }
+
+        self.log.push(QuarantineEntry {
+            original: path.to_path_buf(),
+            quarantined: target.clone(),
+            hashes: store
+                .iter()
+                .filter(|(_, p)| *p == path)
+                .map(|(hash, _)| *hash)
+                .collect(),
+        });
+        if let Err(e) = self.save_log() {
+            self.log.pop();
+            return Err(e);
+        }
+        if let Err(e) = move_file(path, &target) {
+            self.log.pop();
+            let _ = self.save_log();
+            return Err(e);
+        }
+        store.remove_path(path);
+        Ok(())
+    }
+
+    /// Moves the most recently quarantined file back and restores its store entries.
+    ///
+    /// Returns the restored path, or None when there is nothing left to undo. Fails with
+    /// `AlreadyExists` when another file has taken the original path since, which is left as
+    /// it is.
+    pub fn undo(&mut self, store: &mut HashStore) -> Result<Option<PathBuf>, Error> {
+        let entry = match self.log.pop() {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        if let Err(e) = move_file(&entry.quarantined, &entry.original) {
+            let e = if e.kind() == ErrorKind::AlreadyExists {
+                Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("{} exists already", entry.original.display()),
+                )
+            } else {
+                e
+            };
+            self.log.push(entry);
+            return Err(e);
+        }
+        for hash in entry.hashes.iter() {
+            store.add_hash(hash, &entry.original);
+        }
+        self.save_log()?;
+        Ok(Some(entry.original))
+    }
+
+    fn save_log(&self) -> Result<(), Error> {
+        let file = File::create(self.folder.join(UNDO_LOG))?;
+        serde_json::to_writer(file, &self.log)?;
+        Ok(())
+    }
+}
+
+// Never replaces a file at `to`, which a rename would do without asking. Links where possible,
+// copies when the quarantine lives on another drive
+fn move_file(from: &Path, to: &Path) -> Result<(), Error> {
+    match fs::hard_link(from, to) {
+        Ok(()) => return fs::remove_file(from),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => return Err(e),
+        Err(_) => {}
+    }
+    let mut target = OpenOptions::new().write(true).create_new(true).open(to)?;
+    if let Err(e) = std::io::copy(&mut File::open(from)?, &mut target) {
+        drop(target);
+        let _ = fs::remove_file(to);
+        return Err(e);
+    }
+    fs::remove_file(from)
+}
+
+#[test]
+fn quarantine_and_undo() {
+    use crate::ihash::dhash;
+
+    let dir = std::env::temp_dir().join("image-fingerprint-dedupe");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let small = dir.join("bulbasaur.png");
+    let big = dir.join("bulbasaur_big.png");
+    fs::copy("./test/pokemon/bulbasaur.png", &small).unwrap();
+    let image = image::open(&small).unwrap();
+    let (width, height) = image::GenericImageView::dimensions(&image);
+    image
+        .resize(width * 2, height * 2, image::imageops::FilterType::Triangle)
+        .save(&big)
+        .unwrap();
+    let mut store = HashStore::new();
+    for path in [&small, &big] {
+        store.add_hash(&dhash(&image::open(path).unwrap()), path);
+    }
+    let groups = store.duplicate_groups(4);
+    assert_eq!(groups.len(), 1);
+
+    let files: Vec<FileInfo> = groups[0].iter().filter_map(|p| FileInfo::read(p)).collect();
+    let keeper = pick_keeper(&files, &KeepRule::Largest).unwrap();
+    assert_eq!(files[keeper].path, big);
+    assert_eq!(
+        pick_keeper(&files, &KeepRule::InFolder(PathBuf::from("/nowhere"))),
+        None
+    );
+
+    let mut quarantine = Quarantine::open(&dir.join("quarantine")).unwrap();
+    quarantine.quarantine(&mut store, &small).unwrap();
+    assert!(!small.exists());
+    assert_eq!(store.len(), 1);
+
+    // A file that took the original path since is not overwritten
+    fs::write(&small, "newer").unwrap();
+    let mut reopened = Quarantine::open(&dir.join("quarantine")).unwrap();
+    let conflict = reopened.undo(&mut store).unwrap_err();
+    assert_eq!(conflict.kind(), ErrorKind::AlreadyExists);
+    assert_eq!(fs::read_to_string(&small).unwrap(), "newer");
+    assert_eq!(store.len(), 1);
+    assert_eq!(reopened.log().len(), 1);
+
+    fs::remove_file(&small).unwrap();
+    assert_eq!(reopened.undo(&mut store).unwrap(), Some(small.clone()));
+    assert!(small.exists());
+    assert_eq!(store.len(), 2);
+    assert!(reopened.log().is_empty());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[cfg(unix)]
+#[test]
+fn quarantine_non_utf8_name() {
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = std::env::temp_dir().join("image-fingerprint-dedupe-bytes");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(std::ffi::OsStr::from_bytes(b"bulba\xffsaur.png"));
+    fs::copy("./test/pokemon/bulbasaur.png", &path).unwrap();
+    let mut store = HashStore::new();
+    store.add_hash(&IHash::new(7), &path);
+
+    let quarantine_dir = dir.join("quarantine");
+    fs::create_dir_all(&quarantine_dir).unwrap();
+    // Taken, so the quarantined copy gets a numbered name
+    fs::write(quarantine_dir.join(path.file_name().unwrap()), b"").unwrap();
+    let mut quarantine = Quarantine::open(&quarantine_dir).unwrap();
+    quarantine.quarantine(&mut store, &path).unwrap();
+    assert!(!path.exists());
+    assert!(store.is_empty());
+
+    let mut reopened = Quarantine::open(&quarantine_dir).unwrap();
+    let entry = &reopened.log()[0];
+    assert_eq!(entry.original, path);
+    assert_eq!(
+        entry.quarantined.file_name().unwrap().as_bytes(),
+        b"1_bulba\xffsaur.png"
+    );
+    assert!(entry.quarantined.exists());
+    assert_eq!(reopened.undo(&mut store).unwrap(), Some(path.clone()));
+    assert!(path.exists());
+    assert_eq!(store.iter().next(), Some((&IHash::new(7), path.as_path())));
+
+    let _ = fs::remove_dir_all(&dir);
+}
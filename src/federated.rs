@@ -0,0 +1,195 @@
+use std::ops::{Index, IndexMut};
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::fgs::HashStore;
+use crate::ihash::IHash;
+
+/// A match found by [`StoreSet::find_matches`], tagged with the store it came from.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct StoreMatch {
+    /// Position of the store in the set
+    pub store: usize,
+    #[serde(with = "crate::paths")]
+    pub path: PathBuf,
+    pub distance: u32,
+}
+
+/// Several open stores that are searched as one.
+///
+/// Each store can be switched off, which leaves it open but out of searches.
+#[derive(Default, Clone)]
+pub struct StoreSet {
+    stores: Vec<(HashStore, bool)>,
+    // See StoreSet::id, kept in step with stores
+    ids: Vec<usize>,
+    next_id: usize,
+}
+
+impl StoreSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the store, switched on, and returns its position.
+    pub fn push(&mut self, store: HashStore) -> usize {
+        let id = self.new_id();
+        self.stores.push((store, true));
+        self.ids.push(id);
+        self.stores.len() - 1
+    }
+
+    pub fn remove(&mut self, index: usize) -> HashStore {
+        self.ids.remove(index);
+        self.stores.remove(index).0
+    }
+
+    /// Swaps the store at `index` for another one, keeping its switch but not its id.
+    pub fn replace(&mut self, index: usize, store: HashStore) -> HashStore {
+        self.ids[index] = self.new_id();
+        std::mem::replace(&mut self.stores[index].0, store)
+    }
+
+    fn new_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    /// Identifies the store at `index` for as long as it stays in the set, unlike its position
+    /// which shifts when an earlier store is removed.
+    pub fn id(&self, index: usize) -> usize {
+        self.ids[index]
+    }
+
+    /// Where the store with that id is now, None once it was removed or replaced.
+    pub fn position(&self, id: usize) -> Option<usize> {
+        self.ids.iter().position(|other| *other == id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.stores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stores.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &HashStore> {
+        self.stores.iter().map(|(store, _)| store)
+    }
+
+    pub fn is_enabled(&self, index: usize) -> bool {
+        self.stores[index].1
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        self.stores[index].1 = enabled;
+    }
+
+    /// File name of the store, for telling results apart.
+    pub fn name(&self, index: usize) -> String {
+        match self.stores[index]
+            .0
+            .path()
+            .and_then(|path| path.file_name())
+        {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => format!("Unsaved store {}", index + 1),
+        }
+    }
+
+    /// The `size` closest entries over every switched on store.
+    ///
+    /// Equal distances keep the order of the stores in the set.
+    pub fn find_matches(&self, hash_list: &[IHash], size: usize) -> Vec<StoreMatch> {
+        let mut matches: Vec<StoreMatch> = self
+            .stores
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, enabled))| *enabled)
+            .flat_map(|(i, (store, _))| {
+                store
+                    .find_matches(hash_list, size)
+                    .into_iter()
+                    .map(move |m| StoreMatch {
+                        store: i,
+                        path: m.path,
+                        distance: m.distance,
+                    })
+            })
+            .collect();
+        matches.sort_by_key(|m| m.distance);
+        matches.truncate(size);
+        matches
+    }
+}
+
+impl Index<usize> for StoreSet {
+    type Output = HashStore;
+
+    fn index(&self, index: usize) -> &HashStore {
+        &self.stores[index].0
+    }
+}
+
+impl IndexMut<usize> for StoreSet {
+    fn index_mut(&mut self, index: usize) -> &mut HashStore {
+        &mut self.stores[index].0
+    }
+}
+
+#[test]
+fn search_across_stores() {
+    use std::path::Path;
+
+    let hash = |s: &str| IHash::from_str(s);
+    let mut archive = HashStore::new();
+    archive.add_hash(&hash("1"), "/archive/a.png");
+    archive.add_hash(&hash("255"), "/archive/far.png");
+    let mut photos = HashStore::new();
+    photos.add_hash(&hash("3"), "/photos/b.png");
+    photos.add_hash(&hash("1"), "/photos/a.png");
+
+    let mut set = StoreSet::new();
+    set.push(archive);
+    let photos = set.push(photos);
+
+    let matches = set.find_matches(&[hash("1")], 3);
+    let found: Vec<(usize, &Path, u32)> = matches
+        .iter()
+        .map(|m| (m.store, m.path.as_path(), m.distance))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            (0, Path::new("/archive/a.png"), 0),
+            (1, Path::new("/photos/a.png"), 0),
+            (1, Path::new("/photos/b.png"), 1),
+        ]
+    );
+
+    set.set_enabled(photos, false);
+    assert!(set
+        .find_matches(&[hash("1")], 3)
+        .iter()
+        .all(|m| m.store == 0));
+    assert_eq!(set.name(photos), "Unsaved store 2");
+    assert_eq!(set[photos].len(), 2);
+}
+
+#[test]
+fn store_ids_outlive_positions() {
+    let mut set = StoreSet::new();
+    for _ in 0..3 {
+        set.push(HashStore::new());
+    }
+    let (second, third) = (set.id(1), set.id(2));
+
+    set.remove(0);
+    assert_eq!(set.position(second), Some(0));
+    assert_eq!(set.position(third), Some(1));
+    set.replace(0, HashStore::new());
+    assert_eq!(set.position(second), None);
+    assert_ne!(set.id(0), second);
+}
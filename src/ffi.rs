@@ -0,0 +1,378 @@
+//! C interface for computing hashes and using stores from other languages.
+//!
+//! The declarations live in `include/image_fingerprint.h`, regenerate it with
+//! `cbindgen --config cbindgen.toml --output include/image_fingerprint.h` after changing
+//! anything here.
+//!
+//! Only compiled with the `ffi` feature. `capi/` wraps it into `libimage_fingerprint`, built
+//! with `cargo build --release` in that folder.
+//!
+//! Hashes are plain `uint64_t`s holding the same bits as [`IHash`]. Paths are NUL terminated,
+//! raw bytes on Unix and UTF-8 elsewhere. Stores and match lists are handed out as pointers
+//! that have to be released with their `_free` function.
+
+use std::ffi::{c_char, CStr, CString};
+use std::path::PathBuf;
+use std::ptr;
+
+use crate::fgs::HashStore;
+use crate::ihash::{dhash_raw, IHash, PixelFormat};
+
+/// The result of `ifp_store_search`, closest match first.
+pub struct MatchList {
+    matches: Vec<(CString, u32)>,
+}
+
+#[cfg(unix)]
+fn path_from_c(path: &CStr) -> Option<PathBuf> {
+    use std::os::unix::ffi::OsStrExt;
+    Some(std::ffi::OsStr::from_bytes(path.to_bytes()).into())
+}
+
+#[cfg(not(unix))]
+fn path_from_c(path: &CStr) -> Option<PathBuf> {
+    path.to_str().ok().map(PathBuf::from)
+}
+
+// None for paths with a NUL inside, which C cannot be handed
+#[cfg(unix)]
+fn path_to_c(path: &std::path::Path) -> Option<CString> {
+    use std::os::unix::ffi::OsStrExt;
+    CString::new(path.as_os_str().as_bytes()).ok()
+}
+
+#[cfg(not(unix))]
+fn path_to_c(path: &std::path::Path) -> Option<CString> {
+    CString::new(path.to_string_lossy().into_owned()).ok()
+}
+
+unsafe fn path_arg(path: *const c_char) -> Option<PathBuf> {
+    if path.is_null() {
+        return None;
+    }
+    path_from_c(CStr::from_ptr(path))
+}
+
+// The buffer ends after the last pixel of the last row, so it is not read past its padding
+unsafe fn dhash_buffer(
+    pixels: *const u8,
+    width: u32,
+    height: u32,
+    stride: usize,
+    format: PixelFormat,
+    out: *mut u64,
+) -> bool {
+    if pixels.is_null() || out.is_null() || width == 0 || height == 0 {
+        return false;
+    }
+    // Sizes come straight from C, so they may not fit in memory at all
+    let row_len = match (width as usize).checked_mul(format.bytes_per_pixel()) {
+        Some(row_len) if stride >= row_len => row_len,
+        _ => return false,
+    };
+    let len = match (height as usize - 1)
+        .checked_mul(stride)
+        .and_then(|rows| rows.checked_add(row_len))
+    {
+        Some(len) if len <= isize::MAX as usize => len,
+        _ => return false,
+    };
+    let buffer = std::slice::from_raw_parts(pixels, len);
+    match dhash_raw(buffer, width, height, stride, format) {
+        Ok(hash) => {
+            *out = hash.value();
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Hashes an 8 bit RGBA buffer into `out`, the same hash stores hold for the decoded image.
+///
+/// Returns false if a pointer is null, the image is empty, `stride` is shorter than a row or
+/// the buffer would be larger than memory can hold.
+///
+/// # Safety
+///
+/// `pixels` must point to `height` rows of `stride` bytes and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn ifp_dhash_rgba8(
+    pixels: *const u8,
+    width: u32,
+    height: u32,
+    stride: usize,
+    out: *mut u64,
+) -> bool {
+    dhash_buffer(pixels, width, height, stride, PixelFormat::Rgba8, out)
+}
+
+/// Hashes an 8 bit grayscale buffer into `out`, see `ifp_dhash_rgba8`.
+///
+/// # Safety
+///
+/// `pixels` must point to `height` rows of `stride` bytes and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn ifp_dhash_luma8(
+    pixels: *const u8,
+    width: u32,
+    height: u32,
+    stride: usize,
+    out: *mut u64,
+) -> bool {
+    dhash_buffer(pixels, width, height, stride, PixelFormat::Luma8, out)
+}
+
+/// Number of differing bits, against whichever of `hash2` or its inverse is closer.
+#[no_mangle]
+pub extern "C" fn ifp_distance(hash1: u64, hash2: u64) -> u32 {
+    IHash::new(hash1).dist(&IHash::new(hash2))
+}
+
+/// An empty store that has no file yet.
+#[no_mangle]
+pub extern "C" fn ifp_store_new() -> *mut HashStore {
+    Box::into_raw(Box::new(HashStore::new()))
+}
+
+/// Opens a store file, or returns null if it cannot be read.
+///
+/// # Safety
+///
+/// `path` must be a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ifp_store_open(path: *const c_char) -> *mut HashStore {
+    match path_arg(path).map(HashStore::from_file) {
+        Some(Ok(store)) => Box::into_raw(Box::new(store)),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// `store` must come from `ifp_store_new` or `ifp_store_open`, or be null.
+#[no_mangle]
+pub unsafe extern "C" fn ifp_store_free(store: *mut HashStore) {
+    if !store.is_null() {
+        drop(Box::from_raw(store));
+    }
+}
+
+/// # Safety
+///
+/// `store` must be a live store.
+#[no_mangle]
+pub unsafe extern "C" fn ifp_store_len(store: *const HashStore) -> usize {
+    store.as_ref().map_or(0, HashStore::len)
+}
+
+/// # Safety
+///
+/// `store` must be a live store and `path` a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ifp_store_add(
+    store: *mut HashStore,
+    hash: u64,
+    path: *const c_char,
+) -> bool {
+    match (store.as_mut(), path_arg(path)) {
+        (Some(store), Some(path)) => {
+            store.add_hash(&IHash::new(hash), path);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Writes the store to `path`, or back to its own file when `path` is null.
+///
+/// # Safety
+///
+/// `store` must be a live store and `path` a NUL terminated string or null.
+#[no_mangle]
+pub unsafe extern "C" fn ifp_store_save(store: *mut HashStore, path: *const c_char) -> bool {
+    let store = match store.as_mut() {
+        Some(store) => store,
+        None => return false,
+    };
+    let saved = match path_arg(path) {
+        Some(path) => store.save_as(path),
+        None => store.save(),
+    };
+    saved.is_ok()
+}
+
+/// The `size` entries closest to any of the `count` hashes, closest first.
+///
+/// Entries whose path contains a NUL byte are left out, so the list can come up short.
+/// # Safety
+///
+/// `store` must be a live store and `hashes` must point to `count` hashes.
+#[no_mangle]
+pub unsafe extern "C" fn ifp_store_search(
+    store: *const HashStore,
+    hashes: *const u64,
+    count: usize,
+    size: usize,
+) -> *mut MatchList {
+    let store = match store.as_ref() {
+        Some(store) if !hashes.is_null() => store,
+        _ => return ptr::null_mut(),
+    };
+    let hashes: Vec<IHash> = std::slice::from_raw_parts(hashes, count)
+        .iter()
+        .map(|hash| IHash::new(*hash))
+        .collect();
+    let matches = store
+        .find_matches(&hashes, size)
+        .into_iter()
+        .filter_map(|m| Some((path_to_c(&m.path)?, m.distance)))
+        .collect();
+    Box::into_raw(Box::new(MatchList { matches }))
+}
+
+/// # Safety
+///
+/// `matches` must come from `ifp_store_search`.
+#[no_mangle]
+pub unsafe extern "C" fn ifp_matches_len(matches: *const MatchList) -> usize {
+    matches.as_ref().map_or(0, |list| list.matches.len())
+}
+
+/// Path of the match at `index`, valid until the list is freed. Null when out of range.
+///
+/// # Safety
+///
+/// `matches` must come from `ifp_store_search`.
+#[no_mangle]
+pub unsafe extern "C" fn ifp_matches_path(
+    matches: *const MatchList,
+    index: usize,
+) -> *const c_char {
+    match matches.as_ref().and_then(|list| list.matches.get(index)) {
+        Some((path, _)) => path.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Distance of the match at `index`, or `UINT32_MAX` when out of range.
+///
+/// # Safety
+///
+/// `matches` must come from `ifp_store_search`.
+#[no_mangle]
+pub unsafe extern "C" fn ifp_matches_distance(matches: *const MatchList, index: usize) -> u32 {
+    match matches.as_ref().and_then(|list| list.matches.get(index)) {
+        Some((_, distance)) => *distance,
+        None => u32::MAX,
+    }
+}
+
+/// # Safety
+///
+/// `matches` must come from `ifp_store_search`, or be null.
+#[no_mangle]
+pub unsafe extern "C" fn ifp_matches_free(matches: *mut MatchList) {
+    if !matches.is_null() {
+        drop(Box::from_raw(matches));
+    }
+}
+
+#[test]
+fn ffi_matches_library() {
+    use crate::ihash::dhash;
+
+    let image = image::open("./test/pokemon/bulbasaur.png").unwrap();
+    let expected = dhash(&image);
+
+    // Rows padded to a wider stride, as many graphics APIs hand them out
+    let rgba = image.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let stride = width as usize * 4 + 12;
+    let mut padded = vec![0xAB; stride * height as usize];
+    for (y, row) in rgba.as_raw().chunks(width as usize * 4).enumerate() {
+        padded[y * stride..y * stride + row.len()].copy_from_slice(row);
+    }
+    let mut hash = 0;
+    assert!(unsafe { ifp_dhash_rgba8(padded.as_ptr(), width, height, stride, &mut hash) });
+    assert_eq!(hash, expected.value());
+
+    let luma = image.to_luma8();
+    let mut luma_hash = 0;
+    let ok =
+        unsafe { ifp_dhash_luma8(luma.as_ptr(), width, height, width as usize, &mut luma_hash) };
+    assert!(ok);
+    assert_eq!(luma_hash, expected.value());
+    assert!(!unsafe { ifp_dhash_rgba8(padded.as_ptr(), width, height, 4, &mut hash) });
+    let huge = usize::MAX;
+    assert!(!unsafe { ifp_dhash_luma8(luma.as_ptr(), width, height, huge, &mut hash) });
+
+    let other = dhash(&image::open("./test/pokemon/ivysaur.png").unwrap());
+    assert_eq!(ifp_distance(hash, other.value()), expected.dist(&other));
+}
+
+#[cfg(feature = "serde-json")]
+#[test]
+fn ffi_store_round_trip() {
+    let file = std::env::temp_dir().join("image-fingerprint-ffi.json");
+    let file = CString::new(file.to_str().unwrap()).unwrap();
+    let path = CString::new("/photos/a.png").unwrap();
+    unsafe {
+        let store = ifp_store_new();
+        assert!(ifp_store_add(store, 1, path.as_ptr()));
+        assert!(ifp_store_add(store, 255, path.as_ptr()));
+        assert!(ifp_store_save(store, file.as_ptr()));
+        ifp_store_free(store);
+
+        let store = ifp_store_open(file.as_ptr());
+        assert!(!store.is_null());
+        assert_eq!(ifp_store_len(store), 2);
+        let matches = ifp_store_search(store, &3, 1, 1);
+        assert_eq!(ifp_matches_len(matches), 1);
+        assert_eq!(
+            CStr::from_ptr(ifp_matches_path(matches, 0)),
+            path.as_c_str()
+        );
+        assert_eq!(ifp_matches_distance(matches, 0), 1);
+        assert!(ifp_matches_path(matches, 1).is_null());
+        ifp_matches_free(matches);
+        ifp_store_free(store);
+
+        let missing = CString::new("/no/such/store.json").unwrap();
+        assert!(ifp_store_open(missing.as_ptr()).is_null());
+    }
+    let _ = std::fs::remove_file(file.to_str().unwrap());
+}
+
+#[test]
+fn skip_paths_with_nul() {
+    let mut store = HashStore::new();
+    store.add_hash(&IHash::new(1), "/photos/a\0b.png");
+    store.add_hash(&IHash::new(1), "/photos/c.png");
+    unsafe {
+        let matches = ifp_store_search(&store, &1, 1, 2);
+        assert_eq!(ifp_matches_len(matches), 1);
+        assert_eq!(
+            CStr::from_ptr(ifp_matches_path(matches, 0)).to_bytes(),
+            b"/photos/c.png"
+        );
+        ifp_matches_free(matches);
+    }
+}
+
+// Catches a header that was not regenerated after changing anything above
+#[test]
+fn header_is_up_to_date() {
+    use std::path::Path;
+
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let config = cbindgen::Config::from_file(dir.join("cbindgen.toml")).unwrap();
+    let mut generated = vec![];
+    cbindgen::generate_with_config(dir, config)
+        .unwrap()
+        .write(&mut generated);
+    let header = std::fs::read(dir.join("include").join("image_fingerprint.h")).unwrap();
+    assert!(
+        generated == header,
+        "include/image_fingerprint.h is out of date, see cbindgen.toml"
+    );
+}
@@ -1,78 +1,371 @@
-use ::image::{imageops::FilterType, DynamicImage};
-use clipboard_win::{formats, get_clipboard};
+use ::image::{DynamicImage, GenericImageView};
 use iced::{
     alignment::{Horizontal, Vertical},
     executor,
     pure::{
-        widget::{image, Button, Column, Container, Row, Text},
+        widget::{image, Button, Checkbox, Column, Container, PickList, Radio, Row, Slider, Text},
         Application, Element,
     },
     Alignment::{self, Center},
     Command, Length, ProgressBar, Subscription,
 };
+use iced_native::{subscription, window, Event};
 use lib::{
+    animation::FrameSampling,
+    batch,
+    dedupe::{FileInfo, KeepRule, Quarantine},
+    federated::{StoreMatch, StoreSet},
     fgs,
-    ihash::{dhash, dhash_rotations},
+    ihash::{dhash, HashMethod, IHash},
+    merge::ConflictPolicy,
+    pipeline::{self, IMAGE_EXTENSIONS},
+    stats::{StoreStats, VerifyReport},
+    watch::{self, StoreWatcher},
 };
-use rfd::FileDialog;
-use std::{path::PathBuf, str::FromStr, io::Write};
+use rfd::{FileDialog, MessageButtons, MessageDialog};
+use std::{collections::HashSet, path::{Path, PathBuf}, io::Write, time::Duration};
 
+use self::clipboard::Clipboard;
+use self::browse::Browser;
+use self::compare::Compare;
+use self::dedupe::Review;
+use self::stats::StatsPanel;
 use self::hash_dir::{Progress, HashPair};
+use self::session::RecentStore;
+pub use self::session::Session;
 
+mod browse;
+mod clipboard;
+mod compare;
+mod dedupe;
 mod hash_dir;
+mod session;
+mod shell;
+mod stats;
 mod style;
 
 pub struct Gui {
-    hashstore: fgs::HashStore,
-    fingerprint_store_path: Option<PathBuf>,
-    found_paths: Vec<String>,
+    stores: StoreSet,
+    // The store that opening, saving and editing act on
+    active: usize,
+    clipboard: Box<dyn Clipboard>,
+    // None until the first search
+    search_results: Option<Vec<StoreMatch>>,
     found_images: Vec<image::Handle>,
+    top_n: u32,
+    max_distance: u32,
+    hash_method: HashMethod,
+    all_frames: bool,
+    session: Session,
+    compare: Option<Compare>,
+    review: Option<Review>,
+    browser: Option<Browser>,
+    stats: Option<StatsPanel>,
     image_to_process: DynamicImage,
     pasted_image: image::Handle,
     multihashes: Vec<MultiHash>,
     last_id: usize,
+    status: Option<String>,
+    // Only set while watching the source folders of the active store
+    watcher: Option<StoreWatcher>,
+    should_exit: bool,
 }
 
 #[derive(Debug, Clone)]
 pub enum Message {
     SaveImage,
     ClearImage,
+    NewStore,
+    SaveStore,
     SaveHashstoreAs,
+    CloseStore,
+    CloseRequested,
     HashDirectory,
     HashExistingImages,
+    WatchSources(bool),
+    WatchTick,
+    AllFramesChanged(bool),
     AddFile,
+    OpenRecent(RecentStore),
+    AddStore,
+    SelectStore(usize),
+    SearchStore(usize, bool),
+    ImportStore,
     PasteImage,
+    OpenImage,
+    ImageDropped(PathBuf),
     Search,
+    TopNChanged(u32),
+    MaxDistanceChanged(u32),
+    HashMethodChanged(HashMethod),
+    WindowResized(u32, u32),
+    CompareResult(usize),
+    CloseCompare,
+    ReviewDuplicates,
+    DuplicatesFound(Vec<Vec<FileInfo>>),
+    KeepDuplicate(KeepRule),
+    KeepFromFolder,
+    NextGroup,
+    PreviousGroup,
+    UndoQuarantine,
+    CloseReview,
+    BrowseStore,
+    CloseBrowse,
+    BrowseQueryChanged(String),
+    BrowseMissingOnly(bool),
+    BrowsePage(usize),
+    BrowseSelect(IHash, PathBuf, bool),
+    DeleteSelected,
+    RehashSelected,
+    ShowStats,
+    CloseStats,
+    VerifyStore,
+    StoreCounted(StoreStats),
+    StoreVerified(VerifyReport),
+    OpenResult(usize),
+    RevealResult(usize),
+    CopyResultPath(usize),
+    CopyResultImage(usize),
+    BatchSearch,
+    BatchSearched(Result<String, String>),
     MultiHashProgressed((usize, Progress<Vec<HashPair>>)),
 }
 
+// Hashes this close are treated as the same picture when reviewing duplicates
+const DUPLICATE_DISTANCE: u32 = 4;
+
+// How long a file has to stay untouched before the watcher hashes it
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
+
+// Anything smaller is a minimized window, which should not be what the next launch opens with
+const MIN_WINDOW_SIZE: u32 = 100;
+
+impl Gui {
+    fn set_query_image(&mut self, image: DynamicImage) {
+        self.pasted_image = image_handle(&image);
+        self.image_to_process = image;
+    }
+
+    fn search(&mut self) {
+        let hashes = self.hash_method.hashes(&self.image_to_process);
+        let mut matches = self.stores.find_matches(&hashes, self.top_n as usize);
+        matches.retain(|m| m.distance <= self.max_distance);
+        self.found_images = matches
+            .iter()
+            .map(|m| path_handle(&m.path))
+            .collect();
+        self.search_results = Some(matches);
+    }
+
+    // Only asks which side wins when the stores actually disagree on a path
+    fn import_store(&mut self, other: &fgs::HashStore) -> String {
+        let conflicts = self.stores[self.active].diff(other).changed.len();
+        let policy = if conflicts > 0
+            && MessageDialog::new()
+                .set_title("Import fingerprints")
+                .set_description(&format!(
+                    "{} files have a different fingerprint in the imported file. Replace ours with theirs?",
+                    conflicts
+                ))
+                .set_buttons(MessageButtons::YesNo)
+                .show()
+        {
+            ConflictPolicy::KeepTheirs
+        } else {
+            ConflictPolicy::KeepOurs
+        };
+
+        let summary = self.stores[self.active].merge(other, policy);
+        format!(
+            "Imported {} entries, {} conflicting files",
+            summary.added, summary.conflicts
+        )
+    }
+
+    fn start_multihash(&mut self, paths: Vec<PathBuf>, replace: Replace) {
+        let mut multihash = MultiHash::new(self.last_id, self.stores.id(self.active), paths);
+        multihash.replace = replace;
+        if self.all_frames {
+            multihash.frames = FrameSampling::All;
+        }
+        multihash.start();
+        self.multihashes.push(multihash);
+        self.last_id += 1;
+    }
+
+    fn open_store(&mut self, path: PathBuf) {
+        if !self.confirm_discard(self.active) {
+            return;
+        }
+        match fgs::HashStore::from_file(&path) {
+            Ok(store) => {
+                self.set_store(store);
+                self.session.opened(&path);
+                self.save_session();
+            }
+            Err(e) => self.status = Some(format!("Could not open {}: {}", path.display(), e)),
+        }
+    }
+
+    fn set_store(&mut self, store: fgs::HashStore) {
+        self.stores.replace(self.active, store);
+        self.clear_store_views();
+        self.restart_watcher();
+    }
+
+    // Follows the active store, called whenever it or its sources change
+    fn restart_watcher(&mut self) {
+        if self.watcher.is_some() {
+            self.watch_sources(true);
+        }
+    }
+
+    fn watch_sources(&mut self, enabled: bool) {
+        self.watcher = None;
+        if !enabled {
+            return;
+        }
+        let store = &self.stores[self.active];
+        let folders = store.sources().count();
+        if folders == 0 {
+            self.status = Some(String::from(
+                "Nothing to watch, fingerprint a directory first",
+            ));
+            return;
+        }
+        match StoreWatcher::for_store(store, WATCH_DEBOUNCE) {
+            Ok(watcher) => {
+                self.watcher = Some(watcher);
+                self.status = Some(format!("Watching {} folders", folders));
+            }
+            Err(e) => self.status = Some(format!("Could not watch: {}", e)),
+        }
+    }
+
+    // Results and panels refer to entries of the old stores, so they go with them
+    fn clear_store_views(&mut self) {
+        self.search_results = None;
+        self.found_images = vec![];
+        self.review = None;
+        self.browser = None;
+        self.stats = None;
+    }
+
+    // True when there is nothing unsaved or the user is fine with losing it
+    fn confirm_discard(&self, index: usize) -> bool {
+        if !self.stores[index].is_dirty() {
+            return true;
+        }
+        let name = match self.stores[index].path() {
+            Some(path) => path.display().to_string(),
+            None => self.stores.name(index),
+        };
+        MessageDialog::new()
+            .set_title("Unsaved fingerprints")
+            .set_description(&format!("Discard unsaved changes to {}?", name))
+            .set_buttons(MessageButtons::OkCancel)
+            .show()
+    }
+
+    fn save_store_as(&mut self) {
+        if let Some(path) = FileDialog::new().add_filter("", &["json"]).save_file() {
+            self.status = Some(match self.stores[self.active].save_as(&path) {
+                Ok(()) => {
+                    self.session.opened(&path);
+                    self.save_session();
+                    format!("Saved {}", path.display())
+                }
+                Err(e) => format!("Could not save {}: {}", path.display(), e),
+            });
+        }
+    }
+
+    // Called after every settings change, losing the session is not worth bothering anyone about
+    fn save_session(&mut self) {
+        self.session.top_n = self.top_n;
+        self.session.max_distance = self.max_distance;
+        self.session.hash_method = self.hash_method;
+        self.session.all_frames = self.all_frames;
+        let _ = self.session.save();
+    }
+
+    fn result_path(&self, index: usize) -> Option<PathBuf> {
+        let matches = self.search_results.as_ref()?;
+        matches.get(index).map(|m| m.path.clone())
+    }
+
+    fn open_query_image(&mut self, path: &Path) {
+        if let Ok(image) = ::image::open(path) {
+            self.set_query_image(image);
+        }
+    }
+}
+
 impl Application for Gui {
     type Executor = executor::Default;
     type Message = Message;
-    type Flags = ();
-
-    fn new(_flags: ()) -> (Self, Command<Message>) {
-        (
-            Gui {
-                hashstore: fgs::HashStore::new(),
-                fingerprint_store_path: None,
-                image_to_process: DynamicImage::new_rgb8(2, 2),
-                found_paths: vec![],
-                found_images: vec![],
-                pasted_image: image::Handle::from_memory(include_bytes!("../icon.png").to_vec()),
-                multihashes: vec![],
-                last_id: 0,
-            },
-            Command::none(),
-        )
+    type Flags = Session;
+
+    fn new(session: Session) -> (Self, Command<Message>) {
+        let mut stores = StoreSet::new();
+        stores.push(fgs::HashStore::new());
+        let mut gui = Gui {
+            stores,
+            active: 0,
+            clipboard: clipboard::system(),
+            image_to_process: DynamicImage::new_rgb8(2, 2),
+            search_results: None,
+            found_images: vec![],
+            top_n: session.top_n,
+            max_distance: session.max_distance,
+            hash_method: session.hash_method,
+            all_frames: session.all_frames,
+            session: session.clone(),
+            compare: None,
+            review: None,
+            browser: None,
+            stats: None,
+            pasted_image: image::Handle::from_memory(include_bytes!("../icon.png").to_vec()),
+            multihashes: vec![],
+            last_id: 0,
+            status: None,
+            watcher: None,
+            should_exit: false,
+        };
+        if let Some(path) = session.last_store() {
+            gui.open_store(path.to_path_buf());
+        }
+        (gui, Command::none())
     }
 
     fn title(&self) -> String {
         String::from("Image Fingerprint v1.1")
     }
 
+    fn should_exit(&self) -> bool {
+        self.should_exit
+    }
+
     fn subscription(&self) -> Subscription<Message> {
-        Subscription::batch(self.multihashes.iter().map(MultiHash::subscription))
+        let window_events = subscription::events_with(|event, _| match event {
+            Event::Window(window::Event::FileDropped(path)) => Some(Message::ImageDropped(path)),
+            Event::Window(window::Event::Resized { width, height }) => {
+                Some(Message::WindowResized(width, height))
+            }
+            Event::Window(window::Event::CloseRequested) => Some(Message::CloseRequested),
+            _ => None,
+        });
+        let watch_ticks = self
+            .watcher
+            .as_ref()
+            .map(|_| iced::time::every(WATCH_DEBOUNCE).map(|_| Message::WatchTick));
+        Subscription::batch(
+            self.multihashes
+                .iter()
+                .map(MultiHash::subscription)
+                .chain(std::iter::once(window_events))
+                .chain(watch_ticks),
+        )
     }
 
     fn update(&mut self, message: Message) -> Command<Message> {
@@ -82,63 +375,347 @@ impl Application for Gui {
                     .add_filter("Hash storage", &["json"])
                     .pick_file()
                 {
-                    self.hashstore = fgs::HashStore::from_file(file.to_str().unwrap()).unwrap();
-                    self.fingerprint_store_path = Some(file)
+                    self.open_store(file);
+                }
+            }
+            Message::OpenRecent(RecentStore(path)) => self.open_store(path),
+            Message::AddStore => {
+                if let Some(path) = FileDialog::new()
+                    .add_filter("Hash storage", &["json"])
+                    .pick_file()
+                {
+                    match fgs::HashStore::from_file(&path) {
+                        Ok(store) => {
+                            self.stores.push(store);
+                            self.session.opened(&path);
+                            self.save_session();
+                            if self.search_results.is_some() {
+                                self.search();
+                            }
+                        }
+                        Err(e) => {
+                            self.status = Some(format!("Could not open {}: {}", path.display(), e))
+                        }
+                    }
+                }
+            }
+            Message::SelectStore(index) => {
+                self.active = index;
+                self.review = None;
+                self.browser = None;
+                self.stats = None;
+                self.restart_watcher();
+            }
+            Message::SearchStore(index, enabled) => {
+                self.stores.set_enabled(index, enabled);
+                if self.search_results.is_some() {
+                    self.search();
+                }
+            }
+            Message::ImportStore => {
+                if let Some(file) = FileDialog::new()
+                    .add_filter("Hash storage", &["json"])
+                    .pick_file()
+                {
+                    self.status = Some(match fgs::HashStore::from_file(&file) {
+                        Ok(other) => self.import_store(&other),
+                        Err(e) => format!("Could not open {}: {}", file.display(), e),
+                    });
                 }
             }
             Message::PasteImage => {
-                self.pasted_image = if let Ok(data) = get_clipboard(formats::Bitmap) {
-                    self.image_to_process = ::image::load_from_memory(&data).unwrap();
-                    image::Handle::from_memory(data)
-                } else {
-                    self.pasted_image.clone()
+                if let Some(pasted) = self.clipboard.get_image() {
+                    self.set_query_image(pasted);
+                }
+            }
+            Message::OpenImage => {
+                if let Some(path) = FileDialog::new()
+                    .add_filter("Images", IMAGE_EXTENSIONS)
+                    .pick_file()
+                {
+                    self.open_query_image(&path);
                 }
             }
+            Message::ImageDropped(path) => self.open_query_image(&path),
             Message::ClearImage => {
                 self.pasted_image =
                     image::Handle::from_memory(include_bytes!("../icon.png").to_vec())
             }
             Message::SaveImage => {
                 if let Some(path) = FileDialog::new().add_filter("", &["png"]).save_file() {
-                    let spath = path.to_str().unwrap();
-                    match self.image_to_process.save(spath) {
+                    match self.image_to_process.save(&path) {
                         Ok(_) => {
                             let hash = dhash(&self.image_to_process);
-                            self.hashstore.add_hash(&hash, &spath);
-                            let _ = self.hashstore.save();
+                            self.stores[self.active].add_hash(&hash, &path);
                         }
-                        Err(_) => {}
+                        Err(e) => {
+                            self.status =
+                                Some(format!("Could not save {}: {}", path.display(), e));
+                        }
+                    }
+                }
+            }
+            Message::Search => self.search(),
+            Message::TopNChanged(top_n) => {
+                self.top_n = top_n;
+                self.save_session();
+                if self.search_results.is_some() {
+                    self.search();
+                }
+            }
+            Message::MaxDistanceChanged(max_distance) => {
+                self.max_distance = max_distance;
+                self.save_session();
+                if self.search_results.is_some() {
+                    self.search();
+                }
+            }
+            Message::HashMethodChanged(hash_method) => {
+                self.hash_method = hash_method;
+                self.save_session();
+                if self.search_results.is_some() {
+                    self.search();
+                }
+            }
+            // Saved along with the rest of the session on exit
+            Message::WindowResized(width, height) => {
+                if width >= MIN_WINDOW_SIZE && height >= MIN_WINDOW_SIZE {
+                    self.session.window_size = (width, height);
+                }
+            }
+            Message::CompareResult(i) => {
+                if let Some(path) = self.result_path(i) {
+                    self.compare = Compare::new(&self.image_to_process, &path);
+                    if self.compare.is_none() {
+                        self.status = Some(format!("Could not open {}", path.display()));
+                    }
+                }
+            }
+            Message::CloseCompare => self.compare = None,
+            Message::ReviewDuplicates => {
+                if let Some(folder) = FileDialog::new()
+                    .set_title("Quarantine folder for duplicates")
+                    .pick_folder()
+                {
+                    match Quarantine::open(&folder) {
+                        Ok(quarantine) => {
+                            self.review = Some(Review::new(DUPLICATE_DISTANCE, quarantine));
+                            let store = self.stores[self.active].clone();
+                            return Command::perform(
+                                in_background(move || {
+                                    dedupe::find_groups(&store, DUPLICATE_DISTANCE)
+                                }),
+                                Message::DuplicatesFound,
+                            );
+                        }
+                        Err(e) => self.status = Some(format!("Could not open quarantine: {}", e)),
+                    }
+                }
+            }
+            Message::DuplicatesFound(groups) => {
+                if let Some(review) = &mut self.review {
+                    review.set_groups(groups);
+                }
+            }
+            Message::KeepDuplicate(rule) => {
+                if let Some(review) = &mut self.review {
+                    self.status = Some(review.keep(&mut self.stores[self.active], &rule));
+                }
+            }
+            Message::KeepFromFolder => {
+                if let Some(folder) = FileDialog::new().pick_folder() {
+                    return self.update(Message::KeepDuplicate(KeepRule::InFolder(folder)));
+                }
+            }
+            Message::NextGroup => {
+                if let Some(review) = &mut self.review {
+                    review.next();
+                }
+            }
+            Message::PreviousGroup => {
+                if let Some(review) = &mut self.review {
+                    review.previous();
+                }
+            }
+            Message::UndoQuarantine => {
+                if let Some(review) = &mut self.review {
+                    self.status = Some(review.undo(&mut self.stores[self.active]));
+                }
+            }
+            Message::CloseReview => self.review = None,
+            Message::BrowseStore => self.browser = Some(Browser::new(&self.stores[self.active])),
+            Message::CloseBrowse => self.browser = None,
+            Message::BrowseQueryChanged(query) => {
+                if let Some(browser) = &mut self.browser {
+                    browser.set_query(query, &self.stores[self.active]);
+                }
+            }
+            Message::BrowseMissingOnly(missing_only) => {
+                if let Some(browser) = &mut self.browser {
+                    browser.set_missing_only(missing_only, &self.stores[self.active]);
+                }
+            }
+            Message::BrowsePage(page) => {
+                if let Some(browser) = &mut self.browser {
+                    browser.set_page(page, &self.stores[self.active]);
+                }
+            }
+            Message::BrowseSelect(hash, path, selected) => {
+                if let Some(browser) = &mut self.browser {
+                    browser.set_selected(hash, path, selected);
+                }
+            }
+            Message::DeleteSelected => {
+                if let Some(browser) = &mut self.browser {
+                    let selected = browser.take_selected(&self.stores[self.active]);
+                    self.stores[self.active].remove_entries(&selected);
+                    browser.load_thumbnails(&self.stores[self.active]);
+                    self.status = Some(format!("Deleted {} entries", selected.len()));
+                }
+            }
+            Message::RehashSelected => {
+                if let Some(browser) = &mut self.browser {
+                    let selected = browser.take_selected(&self.stores[self.active]);
+                    let paths: Vec<PathBuf> = self.stores[self.active]
+                        .iter()
+                        .enumerate()
+                        .filter(|(i, _)| selected.contains(i))
+                        .map(|(_, (_, path))| path.to_path_buf())
+                        .collect();
+                    self.status = Some(format!("Rehashing {} files", paths.len()));
+                    self.start_multihash(paths, Replace::Paths);
+                }
+            }
+            Message::ShowStats => {
+                self.stats = Some(StatsPanel::new());
+                let store = self.stores[self.active].clone();
+                return Command::perform(in_background(move || store.stats()), Message::StoreCounted);
+            }
+            Message::StoreCounted(counted) => {
+                if let Some(stats) = &mut self.stats {
+                    stats.set_stats(counted);
+                }
+            }
+            Message::CloseStats => self.stats = None,
+            Message::VerifyStore => {
+                if let Some(stats) = &mut self.stats {
+                    stats.start_verify();
+                    let store = self.stores[self.active].clone();
+                    return Command::perform(in_background(move || store.verify()), Message::StoreVerified);
+                }
+            }
+            Message::StoreVerified(report) => {
+                if let Some(stats) = &mut self.stats {
+                    stats.set_verify(report);
+                }
+            }
+            Message::OpenResult(i) => {
+                if let Some(path) = self.result_path(i) {
+                    self.status = shell::open(pipeline::on_disk(&path))
+                        .err()
+                        .map(|e| format!("Could not open {}: {}", path.display(), e));
+                }
+            }
+            Message::RevealResult(i) => {
+                if let Some(path) = self.result_path(i) {
+                    self.status = shell::reveal(pipeline::on_disk(&path))
+                        .err()
+                        .map(|e| format!("Could not show {}: {}", path.display(), e));
+                }
+            }
+            Message::CopyResultPath(i) => {
+                if let Some(path) = self.result_path(i) {
+                    self.status = Some(match self.clipboard.set_text(&path.to_string_lossy()) {
+                        Ok(()) => String::from("Copied path"),
+                        Err(e) => format!("Could not copy path: {}", e),
+                    });
+                }
+            }
+            Message::CopyResultImage(i) => {
+                if let Some(path) = self.result_path(i) {
+                    let copied = pipeline::open_image(&path)
+                        .map_err(|e| e.to_string())
+                        .and_then(|image| self.clipboard.set_image(&image));
+                    self.status = Some(match copied {
+                        Ok(()) => String::from("Copied image"),
+                        Err(e) => format!("Could not copy image: {}", e),
+                    });
+                }
+            }
+            Message::BatchSearch => {
+                let report = FileDialog::new()
+                    .pick_folder()
+                    .zip(
+                        FileDialog::new()
+                            .add_filter("CSV report", &["csv"])
+                            .add_filter("JSON report", &["json"])
+                            .save_file(),
+                    );
+                if let Some((dir, report)) = report {
+                    self.status = Some(String::from("Searching..."));
+                    return Command::perform(
+                        batch_search(self.stores[self.active].clone(), dir, report, self.top_n as usize),
+                        Message::BatchSearched,
+                    );
+                }
+            }
+            Message::BatchSearched(result) => {
+                self.status = Some(result.unwrap_or_else(|e| e));
+            }
+            Message::NewStore => {
+                if self.confirm_discard(self.active) {
+                    if let Some(path) = FileDialog::new().add_filter("", &["json"]).save_file() {
+                        let mut store = fgs::HashStore::new();
+                        self.status = Some(match store.save_as(&path) {
+                            Ok(()) => {
+                                self.set_store(store);
+                                self.session.opened(&path);
+                                self.save_session();
+                                format!("Created {}", path.display())
+                            }
+                            Err(e) => format!("Could not create {}: {}", path.display(), e),
+                        });
                     }
                 }
             }
-            Message::Search => {
-                let hashes = dhash_rotations(&self.image_to_process, FilterType::Triangle);
-                self.found_paths = self.hashstore.find_many(&hashes, 5);
-                self.found_images.clear();
-                for path in self.found_paths.iter() {
-                    let im = image::Handle::from_path(path);
-                    self.found_images.push(im);
+            Message::SaveStore => {
+                if self.stores[self.active].path().is_none() {
+                    self.save_store_as();
+                } else if let Err(e) = self.stores[self.active].save() {
+                    self.status = Some(format!("Could not save: {}", e));
+                } else {
+                    self.status = Some(String::from("Saved"));
+                }
+            }
+            Message::SaveHashstoreAs => self.save_store_as(),
+            Message::CloseStore => {
+                if self.confirm_discard(self.active) {
+                    if self.stores.len() > 1 {
+                        self.stores.remove(self.active);
+                        self.active = 0;
+                        self.clear_store_views();
+                        self.restart_watcher();
+                    } else {
+                        self.set_store(fgs::HashStore::new());
+                    }
                 }
             }
-            Message::SaveHashstoreAs => {
-                if let Some(path) = FileDialog::new().add_filter("", &["json"]).save_file() {
-                    let spath = path.to_str().unwrap();
-                    let _ = self.hashstore.to_file(spath);
-                    self.fingerprint_store_path = Some(PathBuf::from_str(spath).unwrap());
+            Message::CloseRequested => {
+                self.should_exit = (0..self.stores.len()).all(|i| self.confirm_discard(i));
+                if self.should_exit {
+                    self.save_session();
                 }
             }
             Message::HashExistingImages => {
                 if let Some(paths) = FileDialog::new().pick_files() {
-                    self.multihashes.push(MultiHash::new(self.last_id, paths));
-                    if let Some(multihash) = self.multihashes.get_mut(self.last_id) {
-                        multihash.start();
-                    }
-                    self.last_id = self.last_id + 1;
+                    self.start_multihash(paths, Replace::Nothing);
                 }
             }
             // TODO still blocks the main thread
             Message::HashDirectory => {
                 if let Some(path) = FileDialog::new().pick_folder() {
+                    self.stores[self.active].add_source(&path);
+                    self.restart_watcher();
                     let mut paths: Vec<PathBuf> = vec![];
                     if let Ok(dir_iter) = std::fs::read_dir(path) {
                         for entry in dir_iter {
@@ -149,11 +726,31 @@ impl Application for Gui {
                             paths.push(entry.path());
                         }
                     }
-                    self.multihashes.push(MultiHash::new(self.last_id, paths));
-                    if let Some(multihash) = self.multihashes.get_mut(self.last_id) {
-                        multihash.start();
+                    self.start_multihash(paths, Replace::Nothing);
+                }
+            }
+            Message::WatchSources(enabled) => self.watch_sources(enabled),
+            Message::AllFramesChanged(all_frames) => {
+                self.all_frames = all_frames;
+                self.save_session();
+            }
+            // Deletions are applied right away, anything new or changed goes through the usual hashing
+            Message::WatchTick => {
+                if let Some(watcher) = &mut self.watcher {
+                    let (exists, missing): (Vec<_>, Vec<_>) =
+                        watcher.settled().into_iter().partition(|path| path.is_file());
+                    let store = &mut self.stores[self.active];
+                    let removed = watch::sync_paths(store, &missing).removed;
+                    if !exists.is_empty() || removed > 0 {
+                        self.status = Some(format!(
+                            "Watcher: {} changed, {} removed",
+                            exists.len(),
+                            removed
+                        ));
+                    }
+                    if !exists.is_empty() {
+                        self.start_multihash(exists, Replace::Files);
                     }
-                    self.last_id = self.last_id + 1;
                 }
             }
             Message::MultiHashProgressed((id, progress)) => {
@@ -162,13 +759,26 @@ impl Application for Gui {
                     .iter_mut()
                     .find(|multihash| multihash.id == id)
                 {
-                    if let Progress::Advanced(_, newHashes) = &progress {
-                        for (hash, pathbuf) in newHashes {
-                            self.hashstore.add_hash(hash, pathbuf.clone().to_str().unwrap())
+                    // Dropped when the store the job was started for has been closed since
+                    let store = self.stores.position(multihash.store);
+                    if let (Progress::Advanced(_, newHashes), Some(store)) = (&progress, store) {
+                        let store = &mut self.stores[store];
+                        for (hash, path) in newHashes {
+                            match multihash.replace {
+                                Replace::Nothing => store.add_hash(hash, path),
+                                Replace::Paths => store.replace_path(hash, path),
+                                Replace::Files => {
+                                    let file = pipeline::on_disk(path);
+                                    if multihash.replaced.insert(file.to_path_buf()) {
+                                        let stored = watch::entries_of(store, file);
+                                        store.remove_entries(&stored);
+                                    }
+                                    store.add_hash(hash, path)
+                                }
+                            }
                         }
-                        self.hashstore.save();
                     }
-                    
+
                     multihash.progress(progress)
                 }
             }
@@ -178,18 +788,99 @@ impl Application for Gui {
     }
 
     fn view(&self) -> Element<Message> {
+        if let Some(stats) = &self.stats {
+            return Container::new(stats.view())
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .padding(5)
+                .into();
+        }
+
+        if let Some(browser) = &self.browser {
+            let status: Element<_> = match &self.status {
+                Some(status) => message(status),
+                None => Column::new().into(),
+            };
+            let col = Column::new()
+                .spacing(10)
+                .push(status)
+                .push(browser.view(&self.stores[self.active]));
+
+            return Container::new(col)
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .padding(5)
+                .into();
+        }
+
+        if let Some(review) = &self.review {
+            let status: Element<_> = match &self.status {
+                Some(status) => message(status),
+                None => Column::new().into(),
+            };
+            let col = Column::new()
+                .spacing(10)
+                .align_items(Center)
+                .push(review.view())
+                .push(status);
+
+            return Container::new(col)
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .padding(5)
+                .into();
+        }
+
+        if let Some(compare) = &self.compare {
+            let col = Column::new()
+                .spacing(10)
+                .align_items(Center)
+                .push(
+                    Button::new(button_text("Back to Results"))
+                        .on_press(Message::CloseCompare)
+                        .style(style::Button::Primary)
+                        .width(Length::Units(300)),
+                )
+                .push(compare.view());
+
+            return Container::new(col)
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .padding(5)
+                .into();
+        }
+
         let Gui {
-            fingerprint_store_path,
             found_images,
-            found_paths,
+            search_results,
             multihashes,
             ..
         } = self;
 
-        let active_store_filename: Element<_> = match fingerprint_store_path {
-            Some(file) => message(file.to_str().unwrap_or("No file")),
-            None => message("No fingerprint file specified"),
-        };
+        let active_store_filename: Element<_> =
+            match (self.stores[self.active].path(), self.stores[self.active].is_dirty()) {
+                (Some(file), false) => message(&file.to_string_lossy()),
+                (Some(file), true) => message(&format!("{} (unsaved)", file.to_string_lossy())),
+                (None, false) => message("No fingerprint file specified"),
+                (None, true) => message("Unsaved fingerprints"),
+            };
+
+        // Radio picks the store to edit, the checkbox whether it is searched
+        let store_list = (0..self.stores.len()).fold(Column::new().spacing(5), |col, i| {
+            let mut name = self.stores.name(i);
+            if self.stores[i].is_dirty() {
+                name.push_str(" *");
+            }
+            col.push(
+                Row::new()
+                    .spacing(5)
+                    .align_items(Center)
+                    .push(Checkbox::new(self.stores.is_enabled(i), "", move |enabled| {
+                        Message::SearchStore(i, enabled)
+                    }))
+                    .push(Radio::new(i, name, Some(self.active), Message::SelectStore)),
+            )
+        });
 
         let progress_bar = multihashes
             .iter()
@@ -207,22 +898,78 @@ impl Application for Gui {
                 .push(ProgressBar::new(0.0..=100.0, 100.0))
             );
 
-        let image_results: Element<_> = if !found_images.is_empty() {
-            found_images
+        let image_results: Element<_> = match search_results {
+            Some(matches) if !matches.is_empty() => matches
                 .iter()
+                .zip(found_images.iter())
                 .enumerate()
-                .fold(Column::new(), |col, (i, image)| {
+                .fold(Column::new(), |col, (i, (m, image))| {
                     col.push(
                         Row::new()
-                            .push(image::Image::new(image.clone()).height(Length::Units(150)))
-                            .push(message(found_paths.get(i).unwrap())),
+                            .push(
+                                Button::new(
+                                    image::Image::new(image.clone()).height(Length::Units(150)),
+                                )
+                                .on_press(Message::CompareResult(i))
+                                .style(style::Button::Plain),
+                            )
+                            .push(
+                                Column::new()
+                                    .spacing(5)
+                                    .push(message(&m.path.to_string_lossy()))
+                                    .push(message(&format!("in {}", self.stores.name(m.store))))
+                                    .push(message(&format!(
+                                        "{} bits apart ({:.0}% similar)",
+                                        m.distance,
+                                        similarity(m.distance)
+                                    )))
+                                    .push(
+                                        Row::new()
+                                            .spacing(5)
+                                            .push(result_button("Open", Message::OpenResult(i)))
+                                            .push(result_button(
+                                                "Reveal",
+                                                Message::RevealResult(i),
+                                            )),
+                                    )
+                                    .push(
+                                        Row::new()
+                                            .spacing(5)
+                                            .push(result_button(
+                                                "Copy Path",
+                                                Message::CopyResultPath(i),
+                                            ))
+                                            .push(result_button(
+                                                "Copy Image",
+                                                Message::CopyResultImage(i),
+                                            )),
+                                    ),
+                            ),
                     )
                 })
-                .into()
-        } else {
-            message("No images found")
+                .into(),
+            Some(_) => message(&format!(
+                "No close match within {} bits",
+                self.max_distance
+            )),
+            None => message("No images found"),
         };
 
+        let search_settings = Column::new()
+            .spacing(5)
+            .push(message(&format!("Show up to {} results", self.top_n)))
+            .push(Slider::new(1..=20, self.top_n, Message::TopNChanged))
+            .push(message(&format!(
+                "Maximum distance: {} bits",
+                self.max_distance
+            )))
+            .push(Slider::new(0..=32, self.max_distance, Message::MaxDistanceChanged))
+            .push(PickList::new(
+                &HashMethod::ALL[..],
+                Some(self.hash_method),
+                Message::HashMethodChanged,
+            ));
+
         let file_controls_list = Column::new()
             .max_width(300)
             .padding(10)
@@ -240,18 +987,82 @@ impl Application for Gui {
                     .style(style::Button::Additive)
                     .width(Length::Fill),
             )
+            .push(Checkbox::new(
+                self.watcher.is_some(),
+                "Watch source folders",
+                Message::WatchSources,
+            ))
+            .push(Checkbox::new(
+                self.all_frames,
+                "Every frame of animations",
+                Message::AllFramesChanged,
+            ))
             .push(
-                Button::new(button_text("Save Fingerprints File As"))
-                    .on_press(Message::SaveHashstoreAs)
+                Button::new(button_text("Browse Fingerprints"))
+                    .on_press(Message::BrowseStore)
                     .style(style::Button::Primary)
                     .width(Length::Fill),
             )
+            .push(
+                Button::new(button_text("Fingerprint Statistics"))
+                    .on_press(Message::ShowStats)
+                    .style(style::Button::Primary)
+                    .width(Length::Fill),
+            )
+            .push(
+                Button::new(button_text("Review Duplicates"))
+                    .on_press(Message::ReviewDuplicates)
+                    .style(style::Button::Destructive)
+                    .width(Length::Fill),
+            )
+            .push(
+                Button::new(button_text("New Fingerprints File..."))
+                    .on_press(Message::NewStore)
+                    .style(style::Button::Additive)
+                    .width(Length::Fill),
+            )
             .push(
                 Button::new(button_text("Open Fingerprints File"))
                     .on_press(Message::AddFile)
                     .style(style::Button::Primary)
                     .width(Length::Fill),
             )
+            .push(
+                Button::new(button_text("Save Fingerprints File"))
+                    .on_press(Message::SaveStore)
+                    .style(style::Button::Primary)
+                    .width(Length::Fill),
+            )
+            .push(
+                Button::new(button_text("Save Fingerprints File As"))
+                    .on_press(Message::SaveHashstoreAs)
+                    .style(style::Button::Primary)
+                    .width(Length::Fill),
+            )
+            .push(
+                Button::new(button_text("Close Fingerprints File"))
+                    .on_press(Message::CloseStore)
+                    .style(style::Button::Destructive)
+                    .width(Length::Fill),
+            )
+            .push(
+                PickList::new(self.session.recent(), None, Message::OpenRecent)
+                    .placeholder("Recent")
+                    .width(Length::Fill),
+            )
+            .push(
+                Button::new(button_text("Import Fingerprints File..."))
+                    .on_press(Message::ImportStore)
+                    .style(style::Button::Additive)
+                    .width(Length::Fill),
+            )
+            .push(
+                Button::new(button_text("Add Fingerprints File to Search..."))
+                    .on_press(Message::AddStore)
+                    .style(style::Button::Additive)
+                    .width(Length::Fill),
+            )
+            .push(store_list)
             .push(active_store_filename);
 
         let image_viewer = Column::new()
@@ -273,6 +1084,12 @@ impl Application for Gui {
                             .on_press(Message::PasteImage)
                             .style(style::Button::Primary)
                             .width(Length::Fill),
+                    )
+                    .push(
+                        Button::new(button_text("Open Image..."))
+                            .on_press(Message::OpenImage)
+                            .style(style::Button::Primary)
+                            .width(Length::Fill),
                     ),
             )
             .push(
@@ -283,17 +1100,34 @@ impl Application for Gui {
             )
             .push(image::Image::new(self.pasted_image.clone()));
 
+        let status: Element<_> = match &self.status {
+            Some(status) => message(status),
+            None => Column::new().into(),
+        };
+
         let fingerprint_pane = Column::new()
             .max_width(500)
             .padding(10)
             .spacing(5)
             .align_items(Center)
             .push(
-                Button::new(button_text("Search"))
-                    .on_press(Message::Search)
-                    .style(style::Button::Primary)
-                    .width(Length::Fill),
+                Row::new()
+                    .spacing(5)
+                    .push(
+                        Button::new(button_text("Search"))
+                            .on_press(Message::Search)
+                            .style(style::Button::Primary)
+                            .width(Length::Fill),
+                    )
+                    .push(
+                        Button::new(button_text("Batch Search Folder"))
+                            .on_press(Message::BatchSearch)
+                            .style(style::Button::Primary)
+                            .width(Length::Fill),
+                    ),
             )
+            .push(search_settings)
+            .push(status)
             .push(image_results);
 
         let row = Row::new()
@@ -314,8 +1148,25 @@ impl Application for Gui {
 #[derive(Debug)]
 struct MultiHash {
     id: usize,
+    // StoreSet::id of the store the hashes go to
+    store: usize,
     state: State,
     paths: Vec<PathBuf>,
+    replace: Replace,
+    // Files whose old entries were dropped when their first new hash came in
+    replaced: HashSet<PathBuf>,
+    frames: FrameSampling,
+}
+
+// What a hashing job does to the entries the store already has
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Replace {
+    Nothing,
+    // Each hash replaces the entries of its own path
+    Paths,
+    // The first hash from a file replaces every entry of that file, members and frames included,
+    // so a file that cannot be decoded keeps what it had
+    Files,
 }
 
 #[derive(Debug)]
@@ -327,11 +1178,15 @@ enum State {
 }
 
 impl MultiHash {
-    pub fn new(id: usize, paths: Vec<PathBuf>) -> Self {
+    pub fn new(id: usize, store: usize, paths: Vec<PathBuf>) -> Self {
         MultiHash {
             id,
+            store,
             state: State::Idle,
             paths,
+            replace: Replace::Nothing,
+            replaced: HashSet::new(),
+            frames: FrameSampling::First,
         }
     }
 
@@ -363,7 +1218,8 @@ impl MultiHash {
     pub fn subscription(&self) -> Subscription<Message> {
         match self.state {
             State::Hashing { .. } => {
-                hash_dir::files(self.id, self.paths.clone()).map(Message::MultiHashProgressed)
+                hash_dir::files(self.id, self.paths.clone(), self.frames)
+                    .map(Message::MultiHashProgressed)
             }
             _ => Subscription::none(),
         }
@@ -415,3 +1271,209 @@ fn button_text<'a>(message: &str) -> Element<'a, Message> {
     .center_x()
     .into()
 }
+
+// Runs on its own thread so large folders do not freeze the window
+async fn batch_search(
+    store: fgs::HashStore,
+    dir: PathBuf,
+    report: PathBuf,
+    top_n: usize,
+) -> Result<String, String> {
+    let (tx, rx) = iced::futures::channel::oneshot::channel();
+    std::thread::spawn(move || {
+        let result = batch::query_dir(&store, &dir, top_n)
+            .and_then(|result| {
+                result.to_file(&report)?;
+                Ok(format!(
+                    "{} queries written to {}",
+                    result.results.len(),
+                    report.display()
+                ))
+            })
+            .map_err(|e| format!("Batch search failed: {}", e));
+        let _ = tx.send(result);
+    });
+    rx.await
+        .unwrap_or_else(|_| Err(String::from("Batch search failed")))
+}
+
+// Counting, verifying and grouping duplicates go over every entry of the store, which takes
+// a while for large ones
+async fn in_background<T, F>(work: F) -> T
+where
+    T: Default + Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let (tx, rx) = iced::futures::channel::oneshot::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(work());
+    });
+    rx.await.unwrap_or_default()
+}
+
+fn result_button<'a>(label: &str, on_press: Message) -> Button<'a, Message> {
+    Button::new(button_text(label))
+        .on_press(on_press)
+        .style(style::Button::Primary)
+        .width(Length::Fill)
+}
+
+// Comparisons also check the inverted hash, so 32 bits apart is as far as two hashes get
+fn similarity(distance: u32) -> f32 {
+    100.0 * (1.0 - distance as f32 / 32.0)
+}
+
+fn image_handle(image: &DynamicImage) -> image::Handle {
+    image::Handle::from_pixels(image.width(), image.height(), image.to_bgra8().into_raw())
+}
+
+// Archive members and video frames have no file of their own for iced to load
+fn path_handle(path: &Path) -> image::Handle {
+    if pipeline::on_disk(path) != path {
+        if let Ok(image) = pipeline::open_image(path) {
+            return image_handle(&image);
+        }
+    }
+    image::Handle::from_path(path)
+}
+
+#[test]
+fn paste_image_from_clipboard() {
+    let (mut gui, _) = Gui::new(Session::default());
+    gui.clipboard = Box::new(clipboard::MemoryClipboard::with_image(
+        DynamicImage::new_rgb8(4, 3),
+    ));
+    let _ = gui.update(Message::PasteImage);
+    assert_eq!(gui.image_to_process.dimensions(), (4, 3));
+}
+
+#[test]
+fn drop_image_file() {
+    let (mut gui, _) = Gui::new(Session::default());
+    let _ = gui.update(Message::ImageDropped(PathBuf::from("./test/grid_256.png")));
+    assert_eq!(gui.image_to_process.dimensions(), (256, 256));
+}
+
+#[test]
+fn copy_result_image() {
+    let (mut gui, _) = Gui::new(Session::default());
+    gui.search_results = Some(vec![StoreMatch {
+        store: 0,
+        path: PathBuf::from("./test/grid_256.png"),
+        distance: 0,
+    }]);
+    gui.clipboard = Box::new(clipboard::MemoryClipboard::default());
+    let _ = gui.update(Message::CopyResultImage(0));
+    let _ = gui.update(Message::PasteImage);
+    assert_eq!(gui.image_to_process.dimensions(), (256, 256));
+}
+
+#[test]
+fn session_round_trip() {
+    let path = std::env::temp_dir()
+        .join("image-fingerprint-session")
+        .join("session.json");
+    let mut session = Session {
+        top_n: 12,
+        hash_method: HashMethod::DHash,
+        all_frames: true,
+        ..Session::default()
+    };
+    for i in 0..10 {
+        session.opened(Path::new(&format!("store{}.json", i)));
+    }
+    session.opened(Path::new("store5.json"));
+    assert_eq!(session.recent_stores.len(), 8);
+    assert_eq!(session.last_store(), Some(Path::new("store5.json")));
+
+    session.to_file(&path).unwrap();
+    assert_eq!(Session::from_file(&path).unwrap(), session);
+
+    let (gui, _) = Gui::new(session);
+    assert_eq!(gui.top_n, 12);
+    assert_eq!(gui.hash_method, HashMethod::DHash);
+    assert!(gui.all_frames);
+    let _ = std::fs::remove_dir_all(path.parent().unwrap());
+}
+
+#[test]
+fn close_without_unsaved_changes() {
+    let (mut gui, _) = Gui::new(Session::default());
+    gui.update(Message::CloseStore);
+    assert!(gui.stores[gui.active].path().is_none());
+    assert!(!gui.should_exit());
+    gui.update(Message::CloseRequested);
+    assert!(gui.should_exit());
+}
+
+#[test]
+fn minimized_window_keeps_its_size() {
+    let (mut gui, _) = Gui::new(Session::default());
+    gui.update(Message::WindowResized(1000, 700));
+    gui.update(Message::WindowResized(0, 0));
+    assert_eq!(gui.session.window_size, (1000, 700));
+}
+
+#[test]
+fn browse_selection_follows_entries() {
+    let (mut gui, _) = Gui::new(Session::default());
+    let store = &mut gui.stores[gui.active];
+    for (value, name) in [(1, "a.png"), (2, "b.png"), (3, "c.png")] {
+        store.add_hash(&IHash::new(value), name);
+    }
+    let _ = gui.update(Message::BrowseStore);
+    let _ = gui.update(Message::BrowseSelect(IHash::new(3), PathBuf::from("c.png"), true));
+    // Removing an earlier entry shifts every position after it
+    gui.stores[gui.active].remove_path("a.png");
+    let _ = gui.update(Message::DeleteSelected);
+    let left: Vec<&Path> = gui.stores[gui.active].iter().map(|(_, p)| p).collect();
+    assert_eq!(left, [Path::new("b.png")]);
+}
+
+#[test]
+fn hashes_go_to_the_store_they_were_started_for() {
+    let (mut gui, _) = Gui::new(Session::default());
+    gui.start_multihash(vec![PathBuf::from("a.png")], Replace::Nothing);
+    let job = gui.last_id - 1;
+    gui.active = gui.stores.push(fgs::HashStore::new());
+
+    let hashed = || Progress::Advanced(50.0, vec![(IHash::new(1), PathBuf::from("a.png"))]);
+    let _ = gui.update(Message::MultiHashProgressed((job, hashed())));
+    assert_eq!(gui.stores[0].len(), 1);
+    assert!(gui.stores[1].is_empty());
+
+    // Closed stores lose their results instead of handing them to whatever took their place
+    gui.stores.remove(0);
+    gui.active = 0;
+    let _ = gui.update(Message::MultiHashProgressed((job, hashed())));
+    assert!(gui.stores[0].is_empty());
+}
+
+#[test]
+fn changed_files_keep_their_entries_until_rehashed() {
+    let (mut gui, _) = Gui::new(Session::default());
+    let store = &mut gui.stores[gui.active];
+    store.add_hash(&IHash::new(1), "a.zip!/x.png");
+    store.add_hash(&IHash::new(2), "a.zip!/y.png");
+    store.add_hash(&IHash::new(3), "b.png");
+    gui.start_multihash(vec![PathBuf::from("a.zip")], Replace::Files);
+    let job = gui.last_id - 1;
+    assert_eq!(gui.stores[gui.active].len(), 3);
+
+    // Members come in over several chunks, only the first clears what the archive had
+    let hashed = |value, path: &str| {
+        Progress::Advanced(50.0, vec![(IHash::new(value), PathBuf::from(path))])
+    };
+    let _ = gui.update(Message::MultiHashProgressed((job, hashed(4, "a.zip!/x.png"))));
+    let _ = gui.update(Message::MultiHashProgressed((job, hashed(5, "a.zip!/z.png"))));
+    let entries: Vec<(u64, &Path)> = gui.stores[gui.active]
+        .iter()
+        .map(|(h, p)| (h.value(), p))
+        .collect();
+    let expected = [
+        (3, Path::new("b.png")),
+        (4, Path::new("a.zip!/x.png")),
+        (5, Path::new("a.zip!/z.png")),
+    ];
+    assert_eq!(entries, expected);
+}
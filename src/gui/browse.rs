@@ -0,0 +1,224 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use iced::{
+    pure::{
+        widget::{image, Button, Checkbox, Column, Row, Scrollable, TextInput},
+        Element,
+    },
+    Alignment::Center,
+    Length,
+};
+use lib::{fgs::HashStore, ihash::IHash, pipeline::on_disk};
+
+use super::{button_text, message, path_handle, style, Message};
+
+const PAGE_SIZE: usize = 20;
+
+/// Paginated, filterable listing of the entries in a store.
+#[derive(Default)]
+pub struct Browser {
+    query: String,
+    missing_only: bool,
+    page: usize,
+    // Entries rather than positions, which shift whenever the store changes
+    selected: HashSet<(IHash, PathBuf)>,
+    // Files found missing when the filter was turned on, so drawing does not touch the disk
+    missing: HashSet<PathBuf>,
+    // Thumbnails of the entries on the current page
+    thumbnails: HashMap<PathBuf, image::Handle>,
+}
+
+impl Browser {
+    pub fn new(store: &HashStore) -> Self {
+        let mut browser = Browser::default();
+        browser.load_thumbnails(store);
+        browser
+    }
+
+    pub fn set_query(&mut self, query: String, store: &HashStore) {
+        self.query = query;
+        self.page = 0;
+        self.load_thumbnails(store);
+    }
+
+    pub fn set_missing_only(&mut self, missing_only: bool, store: &HashStore) {
+        self.missing_only = missing_only;
+        self.missing = if missing_only {
+            store
+                .iter()
+                .map(|(_, path)| on_disk(path))
+                .filter(|file| !file.exists())
+                .map(Path::to_path_buf)
+                .collect()
+        } else {
+            HashSet::new()
+        };
+        self.page = 0;
+        self.load_thumbnails(store);
+    }
+
+    pub fn set_page(&mut self, page: usize, store: &HashStore) {
+        self.page = page;
+        self.load_thumbnails(store);
+    }
+
+    pub fn set_selected(&mut self, hash: IHash, path: PathBuf, selected: bool) {
+        if selected {
+            self.selected.insert((hash, path));
+        } else {
+            self.selected.remove(&(hash, path));
+        }
+    }
+
+    /// Hands over the positions of the selected entries in the store, leaving nothing selected.
+    pub fn take_selected(&mut self, store: &HashStore) -> Vec<usize> {
+        let selected = std::mem::take(&mut self.selected);
+        store
+            .iter()
+            .enumerate()
+            .filter(|(_, (hash, path))| selected.contains(&(**hash, path.to_path_buf())))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Decodes the thumbnails of the current page ahead of drawing it.
+    pub fn load_thumbnails(&mut self, store: &HashStore) {
+        let matching = self.matching(store);
+        let page = self
+            .page
+            .min(matching.len().div_ceil(PAGE_SIZE).saturating_sub(1));
+        let entries: Vec<(&IHash, &Path)> = store.iter().collect();
+        self.thumbnails = matching
+            .iter()
+            .skip(page * PAGE_SIZE)
+            .take(PAGE_SIZE)
+            .map(|&i| entries[i].1)
+            .map(|path| (path.to_path_buf(), path_handle(path)))
+            .collect();
+    }
+
+    // Matches on path or hash, ignoring case
+    fn matching(&self, store: &HashStore) -> Vec<usize> {
+        let query = self.query.to_lowercase();
+        store
+            .iter()
+            .enumerate()
+            .filter(|(_, (hash, path))| {
+                query.is_empty()
+                    || path.to_string_lossy().to_lowercase().contains(&query)
+                    || hash.to_str().contains(&query)
+            })
+            .filter(|(_, (_, path))| !self.missing_only || self.missing.contains(on_disk(path)))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    pub fn view<'a>(&self, store: &'a HashStore) -> Element<'a, Message> {
+        let matching = self.matching(store);
+        let pages = matching.len().div_ceil(PAGE_SIZE);
+        let page = self.page.min(pages.saturating_sub(1));
+        let entries: Vec<(&IHash, &Path)> = store.iter().collect();
+
+        let controls = Row::new()
+            .spacing(10)
+            .align_items(Center)
+            .push(
+                TextInput::new(
+                    "Filter by path or hash",
+                    &self.query,
+                    Message::BrowseQueryChanged,
+                )
+                .padding(5)
+                .width(Length::Units(400)),
+            )
+            .push(Checkbox::new(
+                self.missing_only,
+                "Missing files only",
+                Message::BrowseMissingOnly,
+            ))
+            .push(browse_button(
+                "Delete Selected",
+                Message::DeleteSelected,
+                style::Button::Destructive,
+            ))
+            .push(browse_button(
+                "Rehash Selected",
+                Message::RehashSelected,
+                style::Button::Additive,
+            ))
+            .push(browse_button(
+                "Done",
+                Message::CloseBrowse,
+                style::Button::Primary,
+            ));
+
+        let rows = matching.iter().skip(page * PAGE_SIZE).take(PAGE_SIZE).fold(
+            Column::new().spacing(5),
+            |col, &i| {
+                let (hash, path) = entries[i];
+                let entry = (*hash, path.to_path_buf());
+                let thumbnail = match self.thumbnails.get(path) {
+                    Some(handle) => handle.clone(),
+                    None => path_handle(path),
+                };
+                col.push(
+                    Row::new()
+                        .spacing(10)
+                        .align_items(Center)
+                        .push(Checkbox::new(
+                            self.selected.contains(&entry),
+                            "",
+                            move |selected| {
+                                Message::BrowseSelect(entry.0, entry.1.clone(), selected)
+                            },
+                        ))
+                        .push(
+                            image::Image::new(thumbnail)
+                                .width(Length::Units(60))
+                                .height(Length::Units(60)),
+                        )
+                        .push(message(&path.to_string_lossy()))
+                        .push(message(&hash.to_str())),
+                )
+            },
+        );
+
+        let mut pager = Row::new().spacing(10).align_items(Center);
+        if page > 0 {
+            pager = pager.push(browse_button(
+                "Previous",
+                Message::BrowsePage(page - 1),
+                style::Button::Primary,
+            ));
+        }
+        pager = pager.push(message(&format!(
+            "Page {} of {}, {} entries, {} selected",
+            page + 1,
+            pages.max(1),
+            matching.len(),
+            self.selected.len()
+        )));
+        if page + 1 < pages {
+            pager = pager.push(browse_button(
+                "Next",
+                Message::BrowsePage(page + 1),
+                style::Button::Primary,
+            ));
+        }
+
+        Column::new()
+            .spacing(10)
+            .push(controls)
+            .push(pager)
+            .push(Scrollable::new(rows).height(Length::Fill))
+            .into()
+    }
+}
+
+fn browse_button<'a>(label: &str, on_press: Message, style: style::Button) -> Button<'a, Message> {
+    Button::new(button_text(label))
+        .on_press(on_press)
+        .style(style)
+        .width(Length::Units(180))
+}
@@ -0,0 +1,132 @@
+use ::image::DynamicImage;
+
+/// Anything the GUI can paste a query image from and copy results to.
+pub trait Clipboard {
+    fn get_image(&mut self) -> Option<DynamicImage>;
+    fn set_text(&mut self, text: &str) -> Result<(), String>;
+    fn set_image(&mut self, image: &DynamicImage) -> Result<(), String>;
+}
+
+/// Returns the clipboard backend for the platform we are running on.
+#[cfg(windows)]
+pub fn system() -> Box<dyn Clipboard> {
+    Box::new(WindowsClipboard)
+}
+
+#[cfg(target_os = "linux")]
+pub fn system() -> Box<dyn Clipboard> {
+    Box::new(LinuxClipboard::new())
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+pub fn system() -> Box<dyn Clipboard> {
+    Box::new(MemoryClipboard::default())
+}
+
+#[cfg(windows)]
+pub struct WindowsClipboard;
+
+#[cfg(windows)]
+impl Clipboard for WindowsClipboard {
+    fn get_image(&mut self) -> Option<DynamicImage> {
+        use clipboard_win::{formats, get_clipboard};
+
+        let data: Vec<u8> = get_clipboard(formats::Bitmap).ok()?;
+        ::image::load_from_memory(&data).ok()
+    }
+
+    fn set_text(&mut self, text: &str) -> Result<(), String> {
+        clipboard_win::set_clipboard_string(text).map_err(|e| e.to_string())
+    }
+
+    fn set_image(&mut self, image: &DynamicImage) -> Result<(), String> {
+        use clipboard_win::{formats, set_clipboard};
+
+        let mut data: Vec<u8> = vec![];
+        image
+            .write_to(&mut data, ::image::ImageOutputFormat::Bmp)
+            .map_err(|e| e.to_string())?;
+        set_clipboard(formats::Bitmap, data).map_err(|e| e.to_string())
+    }
+}
+
+/// X11 and Wayland clipboard, through arboard.
+#[cfg(target_os = "linux")]
+pub struct LinuxClipboard {
+    // None when no display server could be reached
+    inner: Option<arboard::Clipboard>,
+}
+
+#[cfg(target_os = "linux")]
+impl LinuxClipboard {
+    pub fn new() -> Self {
+        LinuxClipboard {
+            inner: arboard::Clipboard::new().ok(),
+        }
+    }
+
+    fn connected(&mut self) -> Result<&mut arboard::Clipboard, String> {
+        self.inner
+            .as_mut()
+            .ok_or_else(|| String::from("No clipboard available"))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Clipboard for LinuxClipboard {
+    fn get_image(&mut self) -> Option<DynamicImage> {
+        let data = self.inner.as_mut()?.get_image().ok()?;
+        let buffer = ::image::RgbaImage::from_raw(
+            data.width as u32,
+            data.height as u32,
+            data.bytes.into_owned(),
+        )?;
+        Some(DynamicImage::ImageRgba8(buffer))
+    }
+
+    fn set_text(&mut self, text: &str) -> Result<(), String> {
+        self.connected()?.set_text(text).map_err(|e| e.to_string())
+    }
+
+    fn set_image(&mut self, image: &DynamicImage) -> Result<(), String> {
+        let rgba = image.to_rgba8();
+        let data = arboard::ImageData {
+            width: rgba.width() as usize,
+            height: rgba.height() as usize,
+            bytes: rgba.into_raw().into(),
+        };
+        self.connected()?.set_image(data).map_err(|e| e.to_string())
+    }
+}
+
+/// Clipboard that only lives in memory, for platforms without a backend and for tests.
+///
+/// Images copied to it can be pasted back as the query, text has nowhere to go.
+#[cfg(any(test, not(any(windows, target_os = "linux"))))]
+#[derive(Default)]
+pub struct MemoryClipboard {
+    image: Option<DynamicImage>,
+}
+
+#[cfg(test)]
+impl MemoryClipboard {
+    pub fn with_image(image: DynamicImage) -> Self {
+        MemoryClipboard { image: Some(image) }
+    }
+}
+
+#[cfg(any(test, not(any(windows, target_os = "linux"))))]
+impl Clipboard for MemoryClipboard {
+    fn get_image(&mut self) -> Option<DynamicImage> {
+        self.image.clone()
+    }
+
+    fn set_text(&mut self, _text: &str) -> Result<(), String> {
+        Err(String::from("No clipboard available"))
+    }
+
+    fn set_image(&mut self, image: &DynamicImage) -> Result<(), String> {
+        self.image = Some(image.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+use std::path::Path;
+
+use ::image::{
+    imageops::{self, FilterType},
+    DynamicImage, GrayImage,
+};
+use iced::{
+    pure::{
+        widget::{image, Column, Container, Row, Space},
+        Element,
+    },
+    Alignment::Center,
+    Length,
+};
+use lib::ihash::{dhash_small_luma, dhash_thumbnail, IHash};
+use lib::pipeline::open_image;
+
+use super::{image_handle, message, style, Message};
+
+// How much the 9x8 thumbnails are blown up by
+const THUMBNAIL_SCALE: u32 = 16;
+
+/// A query image next to one of its search results, down to the bits of their hashes.
+pub struct Compare {
+    path: String,
+    query: image::Handle,
+    candidate: image::Handle,
+    query_thumbnail: image::Handle,
+    candidate_thumbnail: image::Handle,
+    query_hash: IHash,
+    candidate_hash: IHash,
+}
+
+impl Compare {
+    pub fn new(query: &DynamicImage, path: &Path) -> Option<Self> {
+        let candidate = open_image(path).ok()?;
+        let query_small = dhash_thumbnail(query, FilterType::Triangle);
+        let candidate_small = dhash_thumbnail(&candidate, FilterType::Triangle);
+
+        Some(Compare {
+            path: path.to_string_lossy().into_owned(),
+            query: image_handle(query),
+            candidate: image_handle(&candidate),
+            query_thumbnail: thumbnail_handle(&query_small),
+            candidate_thumbnail: thumbnail_handle(&candidate_small),
+            query_hash: dhash_small_luma(&query_small),
+            candidate_hash: dhash_small_luma(&candidate_small),
+        })
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let diff = IHash::diff(&self.query_hash, &self.candidate_hash);
+        // diff may have compared against the inverted candidate, the grid has to show the same
+        let inverted = diff != self.query_hash.value() ^ self.candidate_hash.value();
+        let candidate_bits = if inverted {
+            IHash::new(!self.candidate_hash.value())
+        } else {
+            self.candidate_hash
+        };
+        let diff = IHash::new(diff).bits();
+        let mut summary = format!(
+            "{} of 64 bits differ",
+            IHash::comp(&self.query_hash, &self.candidate_hash)
+        );
+        if inverted {
+            summary.push_str(", compared against the inverted candidate hash");
+        }
+
+        Column::new()
+            .spacing(10)
+            .align_items(Center)
+            .push(
+                Row::new()
+                    .spacing(20)
+                    .push(side(
+                        "Query",
+                        &self.query,
+                        &self.query_thumbnail,
+                        self.query_hash,
+                        &diff,
+                    ))
+                    .push(side(
+                        &self.path,
+                        &self.candidate,
+                        &self.candidate_thumbnail,
+                        candidate_bits,
+                        &diff,
+                    )),
+            )
+            .push(message(&summary))
+            .into()
+    }
+}
+
+fn side<'a>(
+    title: &str,
+    full: &image::Handle,
+    thumbnail: &image::Handle,
+    hash: IHash,
+    diff: &[bool; 64],
+) -> Element<'a, Message> {
+    Column::new()
+        .width(Length::Fill)
+        .spacing(10)
+        .align_items(Center)
+        .push(message(title))
+        .push(image::Image::new(full.clone()).height(Length::Units(300)))
+        .push(image::Image::new(thumbnail.clone()))
+        .push(hash_grid(hash, diff))
+        .into()
+}
+
+// 8 rows of 8 comparisons, in the order dhash_small_luma makes them
+fn hash_grid<'a>(hash: IHash, diff: &[bool; 64]) -> Element<'a, Message> {
+    let bits = hash.bits();
+    (0..8)
+        .fold(Column::new(), |col, row| {
+            col.push((0..8).fold(Row::new(), |cells, i| {
+                let bit = row * 8 + i;
+                cells.push(
+                    Container::new(Space::new(Length::Units(14), Length::Units(14))).style(
+                        style::HashBit {
+                            set: bits[bit],
+                            differs: diff[bit],
+                        },
+                    ),
+                )
+            }))
+        })
+        .into()
+}
+
+fn thumbnail_handle(small: &GrayImage) -> image::Handle {
+    let big = imageops::resize(
+        small,
+        small.width() * THUMBNAIL_SCALE,
+        small.height() * THUMBNAIL_SCALE,
+        FilterType::Nearest,
+    );
+    image_handle(&DynamicImage::ImageLuma8(big))
+}
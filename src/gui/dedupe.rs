@@ -0,0 +1,285 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use iced::{
+    pure::{
+        widget::{image, Button, Column, Row},
+        Element,
+    },
+    Alignment::Center,
+    Length,
+};
+use lib::{
+    dedupe::{pick_keeper, FileInfo, KeepRule, Quarantine},
+    fgs::HashStore,
+    ihash::IHash,
+};
+
+use super::{button_text, message, style, Message};
+
+/// Walks through the duplicate groups of a store one at a time, once they have been found.
+pub struct Review {
+    // None while the groups are still being looked for
+    groups: Option<Vec<Vec<FileInfo>>>,
+    current: usize,
+    max_distance: u32,
+    quarantine: Quarantine,
+}
+
+/// The duplicate groups of a store, compares every pair of entries and reads every file.
+///
+/// Files that are gone or unreadable are left out, and so are groups that end up alone.
+pub fn find_groups(store: &HashStore, max_distance: u32) -> Vec<Vec<FileInfo>> {
+    store
+        .duplicate_groups(max_distance)
+        .iter()
+        .map(|group| group.iter().filter_map(|p| FileInfo::read(p)).collect())
+        .filter(|group: &Vec<FileInfo>| group.len() > 1)
+        .collect()
+}
+
+impl Review {
+    pub fn new(max_distance: u32, quarantine: Quarantine) -> Self {
+        Review {
+            groups: None,
+            current: 0,
+            max_distance,
+            quarantine,
+        }
+    }
+
+    pub fn set_groups(&mut self, groups: Vec<Vec<FileInfo>>) {
+        self.groups = Some(groups);
+        self.current = 0;
+    }
+
+    pub fn next(&mut self) {
+        if self.current + 1 < self.groups().len() {
+            self.current += 1;
+        }
+    }
+
+    fn groups(&self) -> &[Vec<FileInfo>] {
+        self.groups.as_deref().unwrap_or_default()
+    }
+
+    pub fn previous(&mut self) {
+        self.current = self.current.saturating_sub(1);
+    }
+
+    /// Quarantines every file of the current group except the one the rule keeps.
+    pub fn keep(&mut self, store: &mut HashStore, rule: &KeepRule) -> String {
+        let group = match self.groups().get(self.current) {
+            Some(group) => group,
+            None => return String::from("No duplicates left"),
+        };
+        let keeper = match pick_keeper(group, rule) {
+            Some(keeper) => keeper,
+            None => return String::from("No file in this group matches"),
+        };
+
+        let kept = group[keeper].path.clone();
+        let rest: Vec<PathBuf> = group
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != keeper)
+            .map(|(_, file)| file.path.clone())
+            .collect();
+
+        let mut moved: Vec<PathBuf> = vec![];
+        let mut errors: Vec<String> = vec![];
+        for path in rest {
+            match self.quarantine.quarantine(store, &path) {
+                Ok(()) => moved.push(path),
+                Err(e) => errors.push(format!("{}: {}", path.display(), e)),
+            }
+        }
+        let groups = self.groups.get_or_insert_with(Vec::new);
+        groups[self.current].retain(|file| !moved.contains(&file.path));
+        if groups[self.current].len() <= 1 {
+            groups.remove(self.current);
+            self.current = self.current.min(groups.len().saturating_sub(1));
+        }
+
+        let status = format!(
+            "Kept {}, moved {} to {}",
+            kept.display(),
+            moved.len(),
+            self.quarantine.folder().display()
+        );
+        if errors.is_empty() {
+            status
+        } else {
+            format!("{}. Failed: {}", status, errors.join(", "))
+        }
+    }
+
+    pub fn undo(&mut self, store: &mut HashStore) -> String {
+        match self.quarantine.undo(store) {
+            Ok(Some(path)) => {
+                self.restore(store, &path);
+                format!("Restored {}", path.display())
+            }
+            Ok(None) => String::from("Nothing to undo"),
+            Err(e) => format!("Could not undo: {}", e),
+        }
+    }
+
+    // Puts a file back from quarantine into the group of its duplicates, or a new group when
+    // that one was resolved already
+    fn restore(&mut self, store: &HashStore, path: &Path) {
+        let groups = match &mut self.groups {
+            Some(groups) => groups,
+            None => return,
+        };
+        let file = match FileInfo::read(path) {
+            Some(file) => file,
+            None => return,
+        };
+        let hashes: Vec<IHash> = store
+            .iter()
+            .filter(|(_, p)| *p == path)
+            .map(|(hash, _)| *hash)
+            .collect();
+        let mut similar: Vec<&Path> = store
+            .iter()
+            .filter(|(hash, p)| {
+                *p != path && hashes.iter().any(|own| own.dist(hash) <= self.max_distance)
+            })
+            .map(|(_, p)| p)
+            .collect();
+        similar.dedup();
+
+        let existing = groups.iter().position(|group| {
+            group
+                .iter()
+                .any(|other| similar.contains(&other.path.as_path()))
+        });
+        match existing {
+            Some(i) => {
+                groups[i].push(file);
+                self.current = i;
+            }
+            None => {
+                let mut group: Vec<FileInfo> =
+                    similar.into_iter().filter_map(FileInfo::read).collect();
+                if group.is_empty() {
+                    return;
+                }
+                group.push(file);
+                self.current = self.current.min(groups.len());
+                groups.insert(self.current, group);
+            }
+        }
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let controls = Row::new()
+            .spacing(5)
+            .push(review_button("Previous", Message::PreviousGroup))
+            .push(review_button("Next", Message::NextGroup))
+            .push(review_button("Undo", Message::UndoQuarantine))
+            .push(review_button("Done", Message::CloseReview));
+
+        let group = match &self.groups {
+            Some(groups) => groups.get(self.current),
+            None => {
+                return Column::new()
+                    .spacing(10)
+                    .push(controls)
+                    .push(message("Looking for duplicates..."))
+                    .into()
+            }
+        };
+        let group = match group {
+            Some(group) => group,
+            None => {
+                return Column::new()
+                    .spacing(10)
+                    .push(controls)
+                    .push(message("No duplicates left"))
+                    .into()
+            }
+        };
+
+        let keep_rules = Row::new()
+            .spacing(5)
+            .push(keep_button(
+                "Keep Largest",
+                Message::KeepDuplicate(KeepRule::Largest),
+            ))
+            .push(keep_button(
+                "Keep Newest",
+                Message::KeepDuplicate(KeepRule::Newest),
+            ))
+            .push(keep_button("Keep From Folder...", Message::KeepFromFolder));
+
+        let files = group.iter().fold(Row::new().spacing(10), |row, file| {
+            row.push(
+                Column::new()
+                    .width(Length::Units(250))
+                    .spacing(5)
+                    .align_items(Center)
+                    .push(
+                        image::Image::new(image::Handle::from_path(&file.path))
+                            .height(Length::Units(200)),
+                    )
+                    .push(message(&file.path.to_string_lossy()))
+                    .push(message(&format!(
+                        "{} x {}, {}",
+                        file.width,
+                        file.height,
+                        file_size(file.size)
+                    )))
+                    .push(message(&modified(file.modified))),
+            )
+        });
+
+        Column::new()
+            .spacing(10)
+            .align_items(Center)
+            .push(controls)
+            .push(message(&format!(
+                "Group {} of {}",
+                self.current + 1,
+                self.groups().len()
+            )))
+            .push(keep_rules)
+            .push(files)
+            .into()
+    }
+}
+
+fn review_button<'a>(label: &str, on_press: Message) -> Button<'a, Message> {
+    Button::new(button_text(label))
+        .on_press(on_press)
+        .style(style::Button::Primary)
+        .width(Length::Units(150))
+}
+
+fn keep_button<'a>(label: &str, on_press: Message) -> Button<'a, Message> {
+    Button::new(button_text(label))
+        .on_press(on_press)
+        .style(style::Button::Destructive)
+        .width(Length::Units(220))
+}
+
+fn file_size(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1 << 20 => format!("{:.1} MB", b as f64 / (1 << 20) as f64),
+        b if b >= 1 << 10 => format!("{:.1} KB", b as f64 / (1 << 10) as f64),
+        b => format!("{} bytes", b),
+    }
+}
+
+fn modified(time: Option<SystemTime>) -> String {
+    let days = time
+        .and_then(|t| SystemTime::now().duration_since(t).ok())
+        .map(|age| age.as_secs() / (60 * 60 * 24));
+    match days {
+        Some(0) => String::from("Modified today"),
+        Some(1) => String::from("Modified yesterday"),
+        Some(days) => format!("Modified {} days ago", days),
+        None => String::from("Modification time unknown"),
+    }
+}
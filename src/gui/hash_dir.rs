@@ -1,37 +1,31 @@
 use iced_native::subscription;
-use lib::ihash::{dhash, IHash};
+use lib::animation::FrameSampling;
+use lib::ihash::IHash;
 use std::{
+    collections::HashSet,
     hash::Hash,
     path::PathBuf,
     sync::mpsc::{Receiver, TryRecvError},
 };
 
-pub type HashPair = (IHash, PathBuf);
+pub use lib::pipeline::HashPair;
 
 // Just a little utility function
 pub fn files<I: 'static + Hash + Copy + Send + Sync>(
     id: I,
     paths: Vec<PathBuf>,
+    frames: FrameSampling,
 ) -> iced::Subscription<(I, Progress<Vec<HashPair>>)> {
-    subscription::unfold(id, State::Ready(paths), move |state| {
+    subscription::unfold(id, State::Ready(paths, frames), move |state| {
         multihash(id, state)
     })
 }
 
-pub fn hash_files(paths: Vec<PathBuf>) -> Response<HashPair> {
-    let (tx, rx) = std::sync::mpsc::channel();
+pub fn hash_files(paths: Vec<PathBuf>, frames: FrameSampling) -> Response<HashPair> {
     let num_files = paths.len();
-    std::thread::spawn(move || {
-        for path in paths {
-            if let Ok(image) = ::image::open(&path) {
-                let hash = dhash(&image);
-                tx.send((hash, path));
-            }
-        }
-    });
     Response {
         num_files,
-        receiver: rx,
+        receiver: lib::pipeline::hash_files_with(paths, frames),
         contents: vec![],
         complete: false,
     }
@@ -45,8 +39,8 @@ pub struct MultiHash<I> {
 
 async fn multihash<I: Copy>(id: I, state: State) -> (Option<(I, Progress<Vec<HashPair>>)>, State) {
     match state {
-        State::Ready(paths) => {
-            let response = hash_files(paths);
+        State::Ready(paths, frames) => {
+            let response = hash_files(paths, frames);
 
             let total = response.content_length();
             (
@@ -54,19 +48,22 @@ async fn multihash<I: Copy>(id: I, state: State) -> (Option<(I, Progress<Vec<Has
                 State::Hashing {
                     response,
                     total,
-                    num_hashed: 0,
+                    files_done: HashSet::new(),
                 },
             )
         }
         State::Hashing {
             mut response,
             total,
-            num_hashed,
+            mut files_done,
         } => {
             let chunk = response.chunk();
-            let num_hashed = num_hashed + chunk.len();
+            // Archives and animations send a hash per member or frame, progress is by file
+            for (_, path) in chunk.iter() {
+                files_done.insert(lib::pipeline::on_disk(path).to_path_buf());
+            }
 
-            let percentage = (num_hashed as f32 / total as f32) * 100.0;
+            let percentage = (files_done.len() as f32 / total as f32) * 100.0;
 
             if !response.complete {
                 (
@@ -74,7 +71,7 @@ async fn multihash<I: Copy>(id: I, state: State) -> (Option<(I, Progress<Vec<Has
                     State::Hashing {
                         response,
                         total,
-                        num_hashed,
+                        files_done,
                     },
                 )
             } else {
@@ -131,11 +128,12 @@ impl<T: Clone> Response<T> {
 }
 
 pub enum State {
-    Ready(Vec<PathBuf>),
+    Ready(Vec<PathBuf>, FrameSampling),
     Hashing {
         response: Response<(IHash, PathBuf)>,
         total: usize,
-        num_hashed: usize,
+        // Files on disk that at least one hash came from
+        files_done: HashSet<PathBuf>,
     },
     Finished(Response<(IHash, PathBuf)>),
 }
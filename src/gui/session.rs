@@ -0,0 +1,105 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Error;
+use std::path::{Path, PathBuf};
+
+use lib::ihash::HashMethod;
+use serde::{Deserialize, Serialize};
+
+const RECENT_STORES: usize = 8;
+
+/// Whatever the window should look like on the next launch.
+///
+/// Kept in `session.json` under the platform config folder. Missing fields fall back to their
+/// defaults, so older files keep loading.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    /// Most recently opened first, the first one is reopened on startup
+    #[serde(with = "lib::paths::many")]
+    pub recent_stores: Vec<PathBuf>,
+    pub top_n: u32,
+    pub max_distance: u32,
+    pub hash_method: HashMethod,
+    /// Hash every frame of animated GIFs and PNGs instead of just the first
+    pub all_frames: bool,
+    pub window_size: (u32, u32),
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Session {
+            recent_stores: vec![],
+            top_n: 5,
+            max_distance: 32,
+            hash_method: HashMethod::default(),
+            all_frames: false,
+            window_size: (1300, 800),
+        }
+    }
+}
+
+impl Session {
+    fn path() -> Option<PathBuf> {
+        // Tests must not overwrite the session of whoever runs them
+        if cfg!(test) {
+            return None;
+        }
+        dirs::config_dir().map(|dir| dir.join("image-fingerprint").join("session.json"))
+    }
+
+    /// The saved session, or the defaults when there is none or it cannot be read.
+    pub fn load() -> Self {
+        Self::path()
+            .and_then(|path| Self::from_file(&path).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        match Self::path() {
+            Some(path) => self.to_file(&path),
+            None => Ok(()),
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        Ok(serde_json::from_reader(File::open(path)?)?)
+    }
+
+    pub fn to_file(&self, path: &Path) -> Result<(), Error> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        serde_json::to_writer_pretty(File::create(path)?, self)?;
+        Ok(())
+    }
+
+    pub fn last_store(&self) -> Option<&Path> {
+        self.recent_stores.first().map(PathBuf::as_path)
+    }
+
+    /// Moves the store to the front of the recent list.
+    pub fn opened(&mut self, path: &Path) {
+        self.recent_stores.retain(|recent| recent != path);
+        self.recent_stores.insert(0, path.to_path_buf());
+        self.recent_stores.truncate(RECENT_STORES);
+    }
+
+    pub fn recent(&self) -> Vec<RecentStore> {
+        self.recent_stores
+            .iter()
+            .cloned()
+            .map(RecentStore)
+            .collect()
+    }
+}
+
+/// A recent store as a pick list option.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecentStore(pub PathBuf);
+
+impl fmt::Display for RecentStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.display())
+    }
+}
@@ -0,0 +1,47 @@
+use std::io::Error;
+use std::path::Path;
+use std::process::Command;
+
+/// Opens the file with the default application for its type.
+pub fn open(path: &Path) -> Result<(), Error> {
+    opener().arg(path).spawn()?;
+    Ok(())
+}
+
+/// Shows the file in the platform file manager, selected where the platform supports it.
+#[cfg(windows)]
+pub fn reveal(path: &Path) -> Result<(), Error> {
+    let mut select = std::ffi::OsString::from("/select,");
+    select.push(path);
+    Command::new("explorer").arg(select).spawn()?;
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+pub fn reveal(path: &Path) -> Result<(), Error> {
+    Command::new("open").arg("-R").arg(path).spawn()?;
+    Ok(())
+}
+
+// There is no portable way to select a file, so open its folder instead
+#[cfg(not(any(windows, target_os = "macos")))]
+pub fn reveal(path: &Path) -> Result<(), Error> {
+    let folder = path.parent().unwrap_or(path);
+    opener().arg(folder).spawn()?;
+    Ok(())
+}
+
+#[cfg(windows)]
+fn opener() -> Command {
+    Command::new("explorer")
+}
+
+#[cfg(target_os = "macos")]
+fn opener() -> Command {
+    Command::new("open")
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn opener() -> Command {
+    Command::new("xdg-open")
+}
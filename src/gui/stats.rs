@@ -0,0 +1,151 @@
+use std::path::PathBuf;
+
+use iced::{
+    pure::{
+        widget::{Button, Column, Row, Scrollable, Text},
+        Element,
+    },
+    Alignment::Center,
+    Length, ProgressBar,
+};
+use lib::stats::{StoreStats, VerifyReport};
+
+use super::{button_text, message, style, Message};
+
+// Longer lists are cut short, the CLI prints them in full
+const LISTED_PATHS: usize = 10;
+
+/// Statistics of the open store once counted, and the result of verifying it once that has run.
+#[derive(Default)]
+pub struct StatsPanel {
+    stats: Option<StoreStats>,
+    verify: Option<VerifyReport>,
+    verifying: bool,
+}
+
+impl StatsPanel {
+    pub fn new() -> Self {
+        StatsPanel::default()
+    }
+
+    pub fn set_stats(&mut self, stats: StoreStats) {
+        self.stats = Some(stats);
+    }
+
+    pub fn start_verify(&mut self) {
+        self.verifying = true;
+    }
+
+    pub fn set_verify(&mut self, report: VerifyReport) {
+        self.verify = Some(report);
+        self.verifying = false;
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let verify_button = Button::new(button_text(if self.verifying {
+            "Verifying..."
+        } else {
+            "Verify Files"
+        }))
+        .style(style::Button::Additive)
+        .width(Length::Units(200));
+        let verify_button = if self.verifying {
+            verify_button
+        } else {
+            verify_button.on_press(Message::VerifyStore)
+        };
+
+        let controls = Row::new().spacing(5).push(verify_button).push(
+            Button::new(button_text("Done"))
+                .on_press(Message::CloseStats)
+                .style(style::Button::Primary)
+                .width(Length::Units(200)),
+        );
+
+        let stats = match &self.stats {
+            Some(stats) => stats,
+            None => {
+                return Column::new()
+                    .spacing(15)
+                    .align_items(Center)
+                    .push(controls)
+                    .push(message("Counting entries..."))
+                    .into()
+            }
+        };
+
+        let summary = Column::new()
+            .spacing(5)
+            .push(message(&format!(
+                "{} entries, {} unique paths",
+                stats.entries, stats.unique_paths
+            )))
+            .push(path_list(
+                "paths stored more than once",
+                &stats.duplicate_paths,
+            ));
+
+        let largest = stats
+            .nearest_neighbours
+            .iter()
+            .copied()
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let histogram = stats.nearest_neighbours.iter().enumerate().fold(
+            Column::new()
+                .spacing(2)
+                .push(message("Distance to nearest neighbour")),
+            |col, (distance, count)| {
+                col.push(
+                    Row::new()
+                        .spacing(10)
+                        .align_items(Center)
+                        .push(Text::new(distance.to_string()).width(Length::Units(30)))
+                        .push(
+                            ProgressBar::new(0.0..=largest as f32, *count as f32)
+                                .height(Length::Units(12)),
+                        )
+                        .push(Text::new(count.to_string()).width(Length::Units(60))),
+                )
+            },
+        );
+
+        let mut col = Column::new()
+            .spacing(15)
+            .align_items(Center)
+            .push(controls)
+            .push(summary);
+        if let Some(report) = &self.verify {
+            let changed: Vec<PathBuf> = report.changed.iter().map(|c| c.path.clone()).collect();
+            col = col
+                .push(message(&format!(
+                    "Checked {} files: {}",
+                    report.checked,
+                    if report.is_healthy() {
+                        "all present and unchanged"
+                    } else {
+                        "problems found"
+                    }
+                )))
+                .push(path_list("missing files", &report.missing))
+                .push(path_list("unreadable files", &report.unreadable))
+                .push(path_list("files whose hash changed", &changed));
+        }
+
+        Scrollable::new(col.push(histogram)).into()
+    }
+}
+
+fn path_list<'a>(title: &str, paths: &[PathBuf]) -> Element<'a, Message> {
+    let col = paths.iter().take(LISTED_PATHS).fold(
+        Column::new().push(message(&format!("{} {}", paths.len(), title))),
+        |col, path| col.push(message(&path.to_string_lossy())),
+    );
+    if paths.len() > LISTED_PATHS {
+        col.push(message(&format!("and {} more", paths.len() - LISTED_PATHS)))
+            .into()
+    } else {
+        col.into()
+    }
+}
@@ -1,4 +1,4 @@
-use iced::{button, Background, Color, Vector};
+use iced::{button, container, Background, Color, Vector};
 
 const ACTIVE: Color = Color::from_rgb(
     0x72 as f32 / 255.0,
@@ -24,6 +24,7 @@ pub enum Button {
     Primary,
     Additive,
     Destructive,
+    Plain,
 }
 
 impl button::StyleSheet for Button {
@@ -32,6 +33,7 @@ impl button::StyleSheet for Button {
             Button::Primary => (Some(ACTIVE), Color::WHITE),
             Button::Destructive => (Some(RED), Color::WHITE),
             Button::Additive => (Some(GREEN), Color::WHITE),
+            Button::Plain => (None, Color::BLACK),
         };
 
         button::Style {
@@ -50,6 +52,7 @@ impl button::StyleSheet for Button {
             Button::Primary => Some(HOVERED),
             Button::Destructive => Some(HOVERED_RED),
             Button::Additive => Some(HOVERED_GREEN),
+            Button::Plain => Some(Color::from_rgb(0.9, 0.9, 0.9)),
         };
 
         button::Style {
@@ -59,3 +62,26 @@ impl button::StyleSheet for Button {
     }
 }
 
+// One cell of a hash grid
+pub struct HashBit {
+    pub set: bool,
+    pub differs: bool,
+}
+
+impl container::StyleSheet for HashBit {
+    fn style(&self) -> container::Style {
+        let background = if self.set { Color::BLACK } else { Color::WHITE };
+        let (border_width, border_color) = if self.differs {
+            (3.0, RED)
+        } else {
+            (1.0, Color::from_rgb(0.5, 0.5, 0.5))
+        };
+
+        container::Style {
+            background: Some(Background::Color(background)),
+            border_width,
+            border_color,
+            ..container::Style::default()
+        }
+    }
+}
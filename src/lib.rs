@@ -1,13 +1,67 @@
+pub mod animation;
+#[cfg(feature = "archive")]
+pub mod archive;
+#[cfg(feature = "serde-json")]
+pub mod batch;
+#[cfg(feature = "serde-json")]
+pub mod dedupe;
+pub mod federated;
+#[cfg(all(feature = "ffi", not(target_arch = "wasm32")))]
+pub mod ffi;
+pub mod merge;
+pub mod paths;
+pub mod pipeline;
+#[cfg(all(feature = "server", not(target_arch = "wasm32")))]
+pub mod server;
+pub mod stats;
+#[cfg(all(feature = "video", not(target_arch = "wasm32")))]
+pub mod video;
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
+#[cfg(all(feature = "watch", not(target_arch = "wasm32")))]
+pub mod watch;
+
 pub mod ihash {
 
-    use image::{imageops::FilterType, DynamicImage, GrayImage};
+    use std::io::Read;
+
+    use image::error::{
+        ImageError, LimitError, LimitErrorKind, ParameterError, ParameterErrorKind,
+    };
+    use image::{imageops::FilterType, DynamicImage, GrayImage, ImageResult, RgbImage, RgbaImage};
     use serde::{Serialize, Deserialize};
 
+    /// How a query image is turned into the hashes it is searched with.
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
     pub enum HashMethod {
-        DHashRotations
+        /// A single dhash of the image as it is
+        DHash,
+        /// Also matches rotated copies of stored images
+        #[default]
+        DHashRotations,
+    }
+
+    impl HashMethod {
+        pub const ALL: [HashMethod; 2] = [HashMethod::DHash, HashMethod::DHashRotations];
+
+        pub fn hashes(self, image: &DynamicImage) -> Vec<IHash> {
+            match self {
+                HashMethod::DHash => vec![dhash(image)],
+                HashMethod::DHashRotations => dhash_rotations(image, FilterType::Triangle),
+            }
+        }
     }
 
-    #[derive(Default, Debug, Clone, Copy, Serialize, Deserialize)]
+    impl std::fmt::Display for HashMethod {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str(match self {
+                HashMethod::DHash => "dHash",
+                HashMethod::DHashRotations => "dHash with rotations",
+            })
+        }
+    }
+
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
     pub struct IHash {
         value: u64,
     }
@@ -17,6 +71,10 @@ pub mod ihash {
             IHash { value: hash }
         }
 
+        pub fn value(self) -> u64 {
+            self.value
+        }
+
         pub fn from_str(string: &str) -> Self {
             IHash {
                 value: u64::from_str_radix(string, 10).unwrap(),
@@ -29,9 +87,27 @@ pub mod ihash {
 
         // Also checks mirrored hash
         pub fn comp(hash1: &Self, hash2: &Self) -> u32 {
+            IHash::diff(hash1, hash2).count_ones()
+        }
+
+        // The differing bits counted by comp, against whichever of hash2 or its mirror is closer
+        pub fn diff(hash1: &Self, hash2: &Self) -> u64 {
             let xor: u64 = hash1.value ^ hash2.value;
             let xor_inv: u64 = hash1.value ^ u64::MAX ^ hash2.value;
-            u32::min(xor.count_ones(), xor_inv.count_ones())
+            if xor.count_ones() <= xor_inv.count_ones() {
+                xor
+            } else {
+                xor_inv
+            }
+        }
+
+        // Bits in the order dhash_small_luma compared the pixels, row by row
+        pub fn bits(self) -> [bool; 64] {
+            let mut bits = [false; 64];
+            for (i, bit) in bits.iter_mut().enumerate() {
+                *bit = (self.value >> (63 - i)) & 1 == 1;
+            }
+            bits
         }
 
         pub fn dist(self, hash2: &Self) -> u32 {
@@ -59,40 +135,143 @@ pub mod ihash {
 
     pub fn dhash_rotations(image: &DynamicImage, filter: FilterType) -> Vec<IHash> {
         let mut vec: Vec<IHash> = vec![];
-        let gray = DynamicImage::ImageLuma8(image.to_luma8());
+        let mut gray = DynamicImage::ImageLuma8(image.to_luma8());
         vec.push(dhash_small_luma(&gray.resize_exact(9, 8, filter).into_luma8()));
         for _ in 0..=2 {
-            gray.rotate90();
+            gray = gray.rotate90();
             vec.push(dhash_small_luma(&gray.resize_exact(9, 8, filter).into_luma8()));
         }
         vec
     }
 
     pub fn dhash_once(image: &DynamicImage, filter: FilterType) -> IHash {
+        dhash_small_luma(&dhash_thumbnail(image, filter))
+    }
+
+    // The 9x8 grayscale image a dhash is computed from
+    pub fn dhash_thumbnail(image: &DynamicImage, filter: FilterType) -> GrayImage {
         // Not sure if resizing first or grayscaling first is faster
         // let gray = image.resize_exact(9, 8, filter).to_luma8();
-        let gray = DynamicImage::ImageLuma8(image.to_luma8()).resize_exact(9, 8, filter).into_luma8();
-        dhash_small_luma(&gray)
+        DynamicImage::ImageLuma8(image.to_luma8()).resize_exact(9, 8, filter).into_luma8()
     }
 
     pub fn dhash(image: &DynamicImage) -> IHash {
         dhash_once(image, FilterType::Triangle)
     }
+
+    /// How the bytes of a raw pixel buffer are laid out, 8 bits per channel.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum PixelFormat {
+        Luma8,
+        Rgb8,
+        Rgba8,
+    }
+
+    impl PixelFormat {
+        pub fn bytes_per_pixel(self) -> usize {
+            match self {
+                PixelFormat::Luma8 => 1,
+                PixelFormat::Rgb8 => 3,
+                PixelFormat::Rgba8 => 4,
+            }
+        }
+    }
+
+    /// Copies pixels handed out by a decoder or graphics API into an image.
+    ///
+    /// Rows start `stride` bytes apart, anything past the last pixel of a row is skipped.
+    /// Fails when the image is empty, the buffer is too short for its size or the size does
+    /// not fit in memory at all.
+    pub fn image_from_raw(
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+        stride: usize,
+        format: PixelFormat,
+    ) -> ImageResult<DynamicImage> {
+        let mismatch = || {
+            ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::DimensionMismatch,
+            ))
+        };
+        let too_large =
+            || ImageError::Limits(LimitError::from_kind(LimitErrorKind::DimensionError));
+        if width == 0 || height == 0 {
+            return Err(mismatch());
+        }
+        let row_len = (width as usize)
+            .checked_mul(format.bytes_per_pixel())
+            .ok_or_else(too_large)?;
+        if stride < row_len {
+            return Err(mismatch());
+        }
+        let needed = (height as usize - 1)
+            .checked_mul(stride)
+            .and_then(|rows| rows.checked_add(row_len))
+            .ok_or_else(too_large)?;
+        let packed: Vec<u8> = pixels
+            .get(..needed)
+            .ok_or_else(mismatch)?
+            .chunks(stride)
+            .flat_map(|row| &row[..row_len])
+            .copied()
+            .collect();
+        let image = match format {
+            PixelFormat::Luma8 => {
+                GrayImage::from_raw(width, height, packed).map(DynamicImage::ImageLuma8)
+            }
+            PixelFormat::Rgb8 => {
+                RgbImage::from_raw(width, height, packed).map(DynamicImage::ImageRgb8)
+            }
+            PixelFormat::Rgba8 => {
+                RgbaImage::from_raw(width, height, packed).map(DynamicImage::ImageRgba8)
+            }
+        };
+        image.ok_or_else(mismatch)
+    }
+
+    /// The same hash `dhash` gives for the decoded image, see [`image_from_raw`].
+    pub fn dhash_raw(
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+        stride: usize,
+        format: PixelFormat,
+    ) -> ImageResult<IHash> {
+        image_from_raw(pixels, width, height, stride, format).map(|image| dhash(&image))
+    }
+
+    /// Hashes an encoded image, such as the contents of a PNG or JPEG file.
+    pub fn dhash_bytes(bytes: &[u8]) -> ImageResult<IHash> {
+        image::load_from_memory(bytes).map(|image| dhash(&image))
+    }
+
+    /// Reads the stream to its end and hashes it like [`dhash_bytes`].
+    pub fn dhash_reader<R: Read>(mut reader: R) -> ImageResult<IHash> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        dhash_bytes(&bytes)
+    }
 }
 
 pub mod fgs {
 
     use std::cmp::Ordering;
-    use std::collections::BinaryHeap;
+    use std::collections::{BTreeMap, BinaryHeap, HashSet};
     use std::fs::File;
-    use std::io::Error;
+    use std::io::{Error, ErrorKind};
+    use std::path::{Path, PathBuf};
+
+    #[cfg(any(feature = "serde-json", feature = "binary-store"))]
+    use serde::Deserialize;
+    use serde::Serialize;
 
     use crate::ihash::IHash;
 
     #[derive(Clone, Eq, PartialEq, Debug)]
     struct Comparison {
         similarity: usize,
-        path: String,
+        path: PathBuf,
     }
 
     impl PartialOrd for Comparison {
@@ -107,10 +286,102 @@ pub mod fgs {
         }
     }
 
-    #[derive(Default)]
+    /// A stored path and its distance to the hash it was looked up with.
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+    pub struct Match {
+        #[serde(with = "crate::paths")]
+        pub path: PathBuf,
+        pub distance: u32,
+    }
+
+    // Written as a [hash, path] pair
+    #[cfg(feature = "serde-json")]
+    #[derive(Serialize, Deserialize)]
+    struct Entry(IHash, #[serde(with = "crate::paths")] PathBuf);
+
+    #[cfg(any(feature = "serde-json", feature = "binary-store"))]
+    #[derive(Serialize, Deserialize)]
+    struct Root(#[serde(with = "crate::paths")] PathBuf);
+
+    // Paths under a root are written relative to it, with / separators when they are UTF-8
+    #[cfg(any(feature = "serde-json", feature = "binary-store"))]
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum StoredPath {
+        Absolute(#[serde(with = "crate::paths")] PathBuf),
+        Rooted {
+            root: String,
+            #[serde(with = "crate::paths")]
+            path: PathBuf,
+        },
+    }
+
+    // Stores without roots or source folders keep the original bare list format
+    #[cfg(feature = "serde-json")]
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum StoreFile {
+        Plain(Vec<Entry>),
+        Full {
+            #[serde(default)]
+            roots: BTreeMap<String, Root>,
+            #[serde(default, with = "crate::paths::many")]
+            sources: Vec<PathBuf>,
+            hashes: Vec<(IHash, StoredPath)>,
+        },
+    }
+
+    /// Extension of the files `to_file` writes in the binary format.
+    pub const BINARY_EXTENSION: &str = "fgs";
+
+    // Binary store files start with this, JSON ones never do
+    const BINARY_MAGIC: &[u8] = b"FGSBIN1\n";
+
+    // The binary format spells out what the untagged JSON variants leave implicit
+    #[cfg(feature = "binary-store")]
+    #[derive(Serialize, Deserialize)]
+    struct BinaryStore {
+        roots: Vec<(String, Root)>,
+        #[serde(with = "crate::paths::many")]
+        sources: Vec<PathBuf>,
+        hashes: Vec<BinaryEntry>,
+    }
+
+    // The root is left out for absolute paths
+    #[cfg(feature = "binary-store")]
+    #[derive(Serialize, Deserialize)]
+    struct BinaryEntry(
+        IHash,
+        Option<String>,
+        #[serde(with = "crate::paths")] PathBuf,
+    );
+
+    #[cfg(any(not(feature = "serde-json"), not(feature = "binary-store")))]
+    fn missing_feature(name: &str) -> Error {
+        Error::new(
+            ErrorKind::Unsupported,
+            format!("Built without the {} feature", name),
+        )
+    }
+
+    /// Hashes and the paths of the images they came from.
+    ///
+    /// Paths are kept absolute in memory. Paths under one of the store's named roots are
+    /// saved relative to it, so moving a root only needs `relocate_root`.
+    ///
+    /// Nothing is written to disk until `save` or `save_as`; `is_dirty` tells whether there
+    /// are changes that would be lost.
+    ///
+    /// Files are JSON, or a compact binary format with the `binary-store` feature. Reading
+    /// tells the two apart by their contents.
+    #[derive(Default, Clone)]
     pub struct HashStore {
-        hashes: Vec<(IHash, String)>,
-        path: Option<String>,
+        hashes: Vec<(IHash, PathBuf)>,
+        roots: BTreeMap<String, PathBuf>,
+        // Folders the entries were hashed from
+        sources: Vec<PathBuf>,
+        path: Option<PathBuf>,
+        dirty: bool,
     }
 
     impl HashStore {
@@ -118,36 +389,396 @@ pub mod fgs {
             Self::default()
         }
 
-        pub fn from_file(path: &str) -> Result<Self, Error> {
-            let file = File::open(path)?;
-            let data: Vec<(IHash, String)> = serde_json::from_reader(file)?;
-            Ok(Self {
-                hashes: data,
-                path: Some(path.to_string()),
-            })
+        pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+            let data = std::fs::read(&path)?;
+            let mut store = Self {
+                path: Some(path.as_ref().to_path_buf()),
+                ..Self::default()
+            };
+            match data.strip_prefix(BINARY_MAGIC) {
+                Some(data) => store.read_binary(data)?,
+                None => store.read_json(&data)?,
+            }
+            Ok(store)
         }
 
-        pub fn to_file(&self, path: &str) -> Result<&Self, Error> {
-            let file = File::create(path)?;
-            serde_json::to_writer(file, &self.hashes)?;
-            Ok(self)
+        /// Writes the binary format when the file name ends in `.fgs` and JSON otherwise,
+        /// falling back to whichever format the crate was built with.
+        ///
+        /// The store is written next to the file and renamed over it, so a failed write leaves
+        /// the old file as it was.
+        pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<&Self, Error> {
+            let path = path.as_ref();
+            let wants_binary = path.extension().is_some_and(|ext| ext == BINARY_EXTENSION);
+            let binary =
+                cfg!(feature = "binary-store") && (wants_binary || !cfg!(feature = "serde-json"));
+            let mut temp = path.as_os_str().to_owned();
+            temp.push(".tmp");
+            let temp = PathBuf::from(temp);
+
+            let file = File::create(&temp)?;
+            let written = if binary {
+                self.write_binary(file)
+            } else {
+                self.write_json(file)
+            };
+            match written.and_then(|()| std::fs::rename(&temp, path)) {
+                Ok(()) => Ok(self),
+                Err(e) => {
+                    let _ = std::fs::remove_file(&temp);
+                    Err(e)
+                }
+            }
         }
 
-        pub fn save(&self) -> Result<&Self, Error> {
-            if let Some(p) = &self.path {
-                return self.to_file(&p);
+        #[cfg(feature = "serde-json")]
+        fn read_json(&mut self, data: &[u8]) -> Result<(), Error> {
+            match serde_json::from_slice(data)? {
+                StoreFile::Plain(hashes) => {
+                    self.hashes = hashes.into_iter().map(|Entry(h, p)| (h, p)).collect()
+                }
+                StoreFile::Full {
+                    roots,
+                    sources,
+                    hashes,
+                } => {
+                    self.sources = sources;
+                    self.roots = roots
+                        .into_iter()
+                        .map(|(name, Root(root))| (name, root))
+                        .collect();
+                    for (hash, stored) in hashes {
+                        let path = self.resolve(stored)?;
+                        self.hashes.push((hash, path));
+                    }
+                }
             }
-            Err(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "No path set",
-            ))
+            Ok(())
+        }
+
+        #[cfg(not(feature = "serde-json"))]
+        fn read_json(&mut self, _data: &[u8]) -> Result<(), Error> {
+            Err(missing_feature("serde-json"))
+        }
+
+        #[cfg(feature = "serde-json")]
+        fn write_json(&self, file: File) -> Result<(), Error> {
+            if self.roots.is_empty() && self.sources.is_empty() {
+                let data = StoreFile::Plain(
+                    self.hashes
+                        .iter()
+                        .map(|(h, p)| Entry(*h, p.clone()))
+                        .collect(),
+                );
+                serde_json::to_writer(file, &data)?;
+            } else {
+                let data = StoreFile::Full {
+                    roots: self
+                        .roots
+                        .iter()
+                        .map(|(name, root)| (name.clone(), Root(root.clone())))
+                        .collect(),
+                    sources: self.sources.clone(),
+                    hashes: self
+                        .hashes
+                        .iter()
+                        .map(|(hash, p)| (*hash, self.relativize(p)))
+                        .collect(),
+                };
+                serde_json::to_writer(file, &data)?;
+            }
+            Ok(())
+        }
+
+        #[cfg(not(feature = "serde-json"))]
+        fn write_json(&self, _file: File) -> Result<(), Error> {
+            Err(missing_feature("serde-json"))
+        }
+
+        #[cfg(feature = "binary-store")]
+        fn read_binary(&mut self, data: &[u8]) -> Result<(), Error> {
+            let data: BinaryStore =
+                bincode::deserialize(data).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+            self.sources = data.sources;
+            self.roots = data
+                .roots
+                .into_iter()
+                .map(|(name, Root(root))| (name, root))
+                .collect();
+            for BinaryEntry(hash, root, path) in data.hashes {
+                let stored = match root {
+                    Some(root) => StoredPath::Rooted { root, path },
+                    None => StoredPath::Absolute(path),
+                };
+                let path = self.resolve(stored)?;
+                self.hashes.push((hash, path));
+            }
+            Ok(())
+        }
+
+        #[cfg(not(feature = "binary-store"))]
+        fn read_binary(&mut self, _data: &[u8]) -> Result<(), Error> {
+            Err(missing_feature("binary-store"))
+        }
+
+        #[cfg(feature = "binary-store")]
+        fn write_binary(&self, mut file: File) -> Result<(), Error> {
+            use std::io::Write;
+
+            let data = BinaryStore {
+                roots: self
+                    .roots
+                    .iter()
+                    .map(|(name, root)| (name.clone(), Root(root.clone())))
+                    .collect(),
+                sources: self.sources.clone(),
+                hashes: self
+                    .hashes
+                    .iter()
+                    .map(|(hash, p)| match self.relativize(p) {
+                        StoredPath::Absolute(path) => BinaryEntry(*hash, None, path),
+                        StoredPath::Rooted { root, path } => BinaryEntry(*hash, Some(root), path),
+                    })
+                    .collect(),
+            };
+            let bytes =
+                bincode::serialize(&data).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+            file.write_all(BINARY_MAGIC)?;
+            file.write_all(&bytes)
+        }
+
+        #[cfg(not(feature = "binary-store"))]
+        fn write_binary(&self, _file: File) -> Result<(), Error> {
+            Err(missing_feature("binary-store"))
+        }
+
+        /// Declares a named root, replacing any root of the same name.
+        pub fn add_root(&mut self, name: &str, path: &Path) {
+            self.roots.insert(name.to_string(), path.to_path_buf());
+            self.dirty = true;
+        }
+
+        pub fn remove_root(&mut self, name: &str) -> Option<PathBuf> {
+            let removed = self.roots.remove(name);
+            self.dirty |= removed.is_some();
+            removed
+        }
+
+        /// Records a folder the store was built from, so it can be watched for changes.
+        pub fn add_source(&mut self, dir: &Path) {
+            if !self.sources.iter().any(|source| source == dir) {
+                self.sources.push(dir.to_path_buf());
+                self.dirty = true;
+            }
+        }
+
+        pub fn remove_source(&mut self, dir: &Path) {
+            let before = self.sources.len();
+            self.sources.retain(|source| source != dir);
+            self.dirty |= self.sources.len() != before;
+        }
+
+        pub fn sources(&self) -> impl Iterator<Item = &Path> {
+            self.sources.iter().map(PathBuf::as_path)
         }
 
-        pub fn add_hash(&mut self, hash: &IHash, path: &str) {
-            self.hashes.push((hash.clone(), path.to_string()))
+        pub fn roots(&self) -> impl Iterator<Item = (&str, &Path)> {
+            self.roots.iter().map(|(name, root)| (name.as_str(), root.as_path()))
         }
 
-        pub fn find(&self, hash: &IHash) -> Option<&str> {
+        /// Points a root somewhere else, moving every entry that lives under it along.
+        ///
+        /// Returns how many entries moved, or None when there is no root with that name.
+        pub fn relocate_root(&mut self, name: &str, new_path: &Path) -> Option<usize> {
+            self.roots.get(name)?;
+            let mut moved = 0;
+            for i in 0..self.hashes.len() {
+                let relocated = match self.root_of(&self.hashes[i].1) {
+                    Some((root, rel)) if root == name => new_path.join(rel),
+                    _ => continue,
+                };
+                self.hashes[i].1 = relocated;
+                moved += 1;
+            }
+            for i in 0..self.sources.len() {
+                if let Some((root, rel)) = self.root_of(&self.sources[i]) {
+                    if root == name {
+                        self.sources[i] = new_path.join(rel);
+                    }
+                }
+            }
+            self.roots.insert(name.to_string(), new_path.to_path_buf());
+            self.dirty = true;
+            Some(moved)
+        }
+
+        // The most specific root containing the path, and the path relative to it
+        fn root_of<'a>(&self, path: &'a Path) -> Option<(&str, &'a Path)> {
+            self.roots
+                .iter()
+                .filter_map(|(name, root)| {
+                    let rel = path.strip_prefix(root).ok()?;
+                    Some((name.as_str(), root.components().count(), rel))
+                })
+                .max_by_key(|(_, depth, _)| *depth)
+                .map(|(name, _, rel)| (name, rel))
+        }
+
+        #[cfg(any(feature = "serde-json", feature = "binary-store"))]
+        fn relativize(&self, path: &Path) -> StoredPath {
+            match self.root_of(path) {
+                Some((root, rel)) => StoredPath::Rooted {
+                    root: root.to_string(),
+                    path: match rel.iter().map(|c| c.to_str()).collect::<Option<Vec<_>>>() {
+                        Some(components) => PathBuf::from(components.join("/")),
+                        None => rel.to_path_buf(),
+                    },
+                },
+                None => StoredPath::Absolute(path.to_path_buf()),
+            }
+        }
+
+        #[cfg(any(feature = "serde-json", feature = "binary-store"))]
+        fn resolve(&self, stored: StoredPath) -> Result<PathBuf, Error> {
+            match stored {
+                StoredPath::Absolute(path) => Ok(path),
+                StoredPath::Rooted { root, path } => {
+                    let root = self.roots.get(&root).ok_or_else(|| {
+                        Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            format!("Unknown root {}", root),
+                        )
+                    })?;
+                    Ok(match path.to_str() {
+                        Some(path) => path.split('/').fold(root.clone(), |full, c| full.join(c)),
+                        None => root.join(path),
+                    })
+                }
+            }
+        }
+
+        /// Writes the store back to the file it was opened from or last saved as.
+        pub fn save(&mut self) -> Result<(), Error> {
+            match &self.path {
+                Some(p) => self.to_file(p)?,
+                None => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        "No path set",
+                    ))
+                }
+            };
+            self.dirty = false;
+            Ok(())
+        }
+
+        /// Writes the store to `path`, which becomes the file `save` writes to.
+        pub fn save_as<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+            self.to_file(&path)?;
+            self.path = Some(path.as_ref().to_path_buf());
+            self.dirty = false;
+            Ok(())
+        }
+
+        pub fn path(&self) -> Option<&Path> {
+            self.path.as_deref()
+        }
+
+        /// Whether anything changed since the store was opened or last saved.
+        pub fn is_dirty(&self) -> bool {
+            self.dirty
+        }
+
+        pub fn add_hash<P: AsRef<Path>>(&mut self, hash: &IHash, path: P) {
+            self.hashes.push((hash.clone(), path.as_ref().to_path_buf()));
+            self.dirty = true;
+        }
+
+        // Returns the hashes that were stored for the path
+        pub fn remove_path<P: AsRef<Path>>(&mut self, path: P) -> Vec<IHash> {
+            let mut removed: Vec<IHash> = vec![];
+            self.hashes.retain(|(h, p)| {
+                if p == path.as_ref() {
+                    removed.push(*h);
+                    false
+                } else {
+                    true
+                }
+            });
+            self.dirty |= !removed.is_empty();
+            removed
+        }
+
+        // Hashes the path again, dropping whatever was stored for it before
+        pub fn replace_path<P: AsRef<Path>>(&mut self, hash: &IHash, path: P) {
+            self.remove_path(&path);
+            self.add_hash(hash, path);
+        }
+
+        // Indices are positions in iter()
+        pub fn remove_entries(&mut self, indices: &[usize]) {
+            let before = self.hashes.len();
+            let indices: HashSet<usize> = indices.iter().copied().collect();
+            let mut i = 0;
+            self.hashes.retain(|_| {
+                let keep = !indices.contains(&i);
+                i += 1;
+                keep
+            });
+            self.dirty |= self.hashes.len() != before;
+        }
+
+        pub fn iter(&self) -> impl Iterator<Item = (&IHash, &Path)> {
+            self.hashes.iter().map(|(h, p)| (h, p.as_path()))
+        }
+
+        pub fn len(&self) -> usize {
+            self.hashes.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.hashes.is_empty()
+        }
+
+        /// Groups paths whose hashes are chained together by distances of at most `max_distance`.
+        ///
+        /// Only groups with at least two different paths are returned.
+        pub fn duplicate_groups(&self, max_distance: u32) -> Vec<Vec<PathBuf>> {
+            // Union-find over entry indices
+            let mut parent: Vec<usize> = (0..self.hashes.len()).collect();
+            fn root(parent: &mut [usize], mut i: usize) -> usize {
+                while parent[i] != i {
+                    parent[i] = parent[parent[i]];
+                    i = parent[i];
+                }
+                i
+            }
+            for i in 0..self.hashes.len() {
+                for j in (i + 1)..self.hashes.len() {
+                    if self.hashes[i].0.dist(&self.hashes[j].0) <= max_distance {
+                        let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                        parent[a] = b;
+                    }
+                }
+            }
+
+            let mut groups: Vec<Vec<PathBuf>> = vec![];
+            let mut group_of_root: Vec<Option<usize>> = vec![None; self.hashes.len()];
+            for (i, (_, path)) in self.hashes.iter().enumerate() {
+                let r = root(&mut parent, i);
+                let group = *group_of_root[r].get_or_insert_with(|| {
+                    groups.push(vec![]);
+                    groups.len() - 1
+                });
+                if !groups[group].contains(path) {
+                    groups[group].push(path.clone());
+                }
+            }
+            groups.retain(|group| group.len() > 1);
+            groups
+        }
+
+        pub fn find(&self, hash: &IHash) -> Option<&Path> {
             for (h, p) in self.hashes.iter() {
                 if hash.dist(h) == 0 {
                     return Some(p);
@@ -156,40 +787,47 @@ pub mod fgs {
             None
         }
 
-        pub fn find_many(&self, hash_list: &Vec<IHash>, size: usize) -> Vec<String> {
+        pub fn find_many(&self, hash_list: &Vec<IHash>, size: usize) -> Vec<PathBuf> {
+            self.find_matches(hash_list, size)
+                .into_iter()
+                .map(|m| m.path)
+                .collect()
+        }
+
+        // Each stored hash is scored by its closest hash in the list
+        pub fn find_matches(&self, hash_list: &[IHash], size: usize) -> Vec<Match> {
             let mut bheap: BinaryHeap<Comparison> = BinaryHeap::new();
             for (h, p) in self.hashes.iter() {
-                let mut smallest = 100 - hash_list[0].dist(h);
-                for hash in hash_list {
-                    let d = 100 - hash.dist(h);
-                    if d < smallest {
-                        smallest = d;
-                    }
+                let closest = hash_list.iter().map(|hash| hash.dist(h)).min();
+                if let Some(distance) = closest {
+                    bheap.push(Comparison {
+                        similarity: 100 - distance as usize,
+                        path: p.clone(),
+                    })
                 }
-                bheap.push(Comparison {
-                    similarity: smallest as usize,
-                    path: String::from(p),
-                })
             }
-            let mut result: Vec<String> = vec![];
+            let mut result: Vec<Match> = vec![];
             for _ in 0..size {
                 if let Some(comp) = bheap.pop() {
-                    result.push(comp.path);
+                    result.push(Match {
+                        distance: 100 - comp.similarity as u32,
+                        path: comp.path,
+                    });
                 }
             }
             result
         }
 
-        pub fn find_heap(&self, hash: &IHash, size: usize) -> Vec<String> {
+        pub fn find_heap(&self, hash: &IHash, size: usize) -> Vec<PathBuf> {
             let mut bheap: BinaryHeap<Comparison> = BinaryHeap::new();
             for (h, p) in self.hashes.iter() {
                 bheap.push(Comparison {
                     similarity: 100 - hash.dist(h) as usize,
-                    path: String::from(p),
+                    path: p.clone(),
                 })
             }
 
-            let mut result: Vec<String> = vec![];
+            let mut result: Vec<PathBuf> = vec![];
             for _ in 0..size {
                 if let Some(comp) = bheap.pop() {
                     result.push(comp.path);
@@ -214,6 +852,72 @@ fn distance_test() {
     assert_eq!(4, IHash::comp(&IHash::from_str("7"), &IHash::from_str("8")));
 }
 
+#[test]
+fn diff_test() {
+    use ihash::IHash;
+    let a = IHash::from_str("7");
+    let b = IHash::from_str("8");
+    assert_eq!(IHash::diff(&a, &b), 15);
+    assert_eq!(IHash::diff(&a, &b).count_ones(), IHash::comp(&a, &b));
+    let bits = IHash::new(IHash::diff(&a, &b)).bits();
+    assert_eq!(bits.iter().filter(|b| **b).count(), 4);
+    assert!(bits[60] && bits[63] && !bits[59]);
+}
+
+#[test]
+fn hashstore_edit_entries() {
+    use fgs::HashStore;
+    use ihash::IHash;
+    use std::path::Path;
+
+    let mut store = HashStore::new();
+    store.add_hash(&IHash::from_str("1"), "a.png");
+    store.add_hash(&IHash::from_str("2"), "b.png");
+    store.add_hash(&IHash::from_str("3"), "a.png");
+    store.add_hash(&IHash::from_str("4"), "c.png");
+
+    store.replace_path(&IHash::from_str("5"), "a.png");
+    assert_eq!(store.len(), 3);
+    assert_eq!(store.find(&IHash::from_str("5")), Some(Path::new("a.png")));
+
+    store.remove_entries(&[0, 2]);
+    let paths: Vec<&Path> = store.iter().map(|(_, p)| p).collect();
+    assert_eq!(paths, vec![Path::new("c.png")]);
+}
+
+#[cfg(feature = "serde-json")]
+#[test]
+fn hashstore_roots() {
+    use fgs::HashStore;
+    use ihash::IHash;
+    use std::path::{Path, PathBuf};
+
+    let fname = "./test/roots.json";
+    let photos: PathBuf = ["/", "mnt", "photos"].iter().collect();
+    let mut store = HashStore::new();
+    store.add_root("photos", &photos);
+    store.add_hash(&IHash::from_str("9"), photos.join("trip").join("a.png"));
+    store.add_hash(&IHash::from_str("7"), "./elsewhere.png");
+    store.to_file(fname).unwrap();
+
+    let saved = std::fs::read_to_string(fname).unwrap();
+    assert!(saved.contains(r#"{"root":"photos","path":"trip/a.png"}"#));
+
+    let mut loaded = HashStore::from_file(fname).unwrap();
+    let moved: PathBuf = ["/", "media", "share"].iter().collect();
+    assert_eq!(loaded.relocate_root("photos", &moved), Some(1));
+    assert_eq!(loaded.relocate_root("nothing", &moved), None);
+    assert_eq!(
+        loaded.find(&IHash::from_str("9")).unwrap(),
+        moved.join("trip").join("a.png")
+    );
+    assert_eq!(
+        loaded.find(&IHash::from_str("7")),
+        Some(Path::new("./elsewhere.png"))
+    );
+    let _ = std::fs::remove_file(fname);
+}
+
 #[test]
 fn dhash_test() {
     use image::io::Reader;
@@ -225,6 +929,94 @@ fn dhash_test() {
     ihash::dhash(&image);
 }
 
+#[test]
+fn dhash_without_files() {
+    use ihash::{dhash, dhash_bytes, dhash_raw, dhash_reader, PixelFormat};
+
+    let path = "./test/pokemon/bulbasaur.png";
+    let expected = dhash(&image::open(path).unwrap());
+    let bytes = std::fs::read(path).unwrap();
+    assert_eq!(dhash_bytes(&bytes).unwrap(), expected);
+    let file = std::fs::File::open(path).unwrap();
+    assert_eq!(dhash_reader(file).unwrap(), expected);
+    assert!(dhash_bytes(b"not an image").is_err());
+
+    // Rows padded past the last pixel, as video decoders tend to hand them out
+    let rgba = image::open(path).unwrap().to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let stride = width as usize * 4 + 16;
+    let mut padded = vec![0xAB; stride * height as usize];
+    for (y, row) in rgba.as_raw().chunks(width as usize * 4).enumerate() {
+        padded[y * stride..y * stride + row.len()].copy_from_slice(row);
+    }
+    let hash = dhash_raw(&padded, width, height, stride, PixelFormat::Rgba8);
+    assert_eq!(hash.ok(), Some(expected));
+
+    let luma = image::open(path).unwrap().to_luma8();
+    let stride = width as usize;
+    let hash = dhash_raw(luma.as_raw(), width, height, stride, PixelFormat::Luma8);
+    assert_eq!(hash.ok(), Some(expected));
+    let short = &luma.as_raw()[1..];
+    let hash = dhash_raw(short, width, height, stride, PixelFormat::Luma8);
+    assert!(hash.is_err());
+    let hash = dhash_raw(&padded, width, height, 4, PixelFormat::Rgba8);
+    assert!(hash.is_err());
+    // Sizes that overflow instead of asking for more than the buffer has
+    let hash = dhash_raw(&padded, width, height, usize::MAX, PixelFormat::Rgba8);
+    assert!(matches!(hash, Err(image::ImageError::Limits(_))));
+}
+
+#[test]
+fn dhash_rotations_find_rotated_copies() {
+    use fgs::HashStore;
+    use ihash::{dhash, HashMethod};
+    use std::path::Path;
+
+    let path = "./test/pokemon/bulbasaur.png";
+    let image = image::open(path).unwrap();
+    let mut store = HashStore::new();
+    store.add_hash(&dhash(&image), path);
+
+    let rotated = image.rotate90();
+    let hashes = HashMethod::DHashRotations.hashes(&rotated);
+    assert_eq!(hashes.len(), 4);
+    assert!(hashes.iter().skip(1).any(|h| *h != hashes[0]));
+    let found = store.find_matches(&hashes, 1);
+    assert_eq!(found[0].path, Path::new(path));
+    assert!(found[0].distance <= 2);
+}
+
+#[cfg(feature = "binary-store")]
+#[test]
+fn hashstore_binary_round_trip() {
+    use fgs::HashStore;
+    use ihash::IHash;
+    use std::path::PathBuf;
+
+    let fname = "./test/binary.fgs";
+    let photos: PathBuf = ["/", "mnt", "photos"].iter().collect();
+    let mut store = HashStore::new();
+    store.add_root("photos", &photos);
+    store.add_source(&photos);
+    store.add_hash(&IHash::from_str("9"), photos.join("trip").join("a.png"));
+    store.add_hash(&IHash::from_str("7"), "./elsewhere.png");
+    store.to_file(fname).unwrap();
+    assert!(std::fs::read(fname).unwrap().starts_with(b"FGSBIN1\n"));
+
+    let loaded = HashStore::from_file(fname).unwrap();
+    assert_eq!(
+        loaded.iter().collect::<Vec<_>>(),
+        store.iter().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        loaded.roots().collect::<Vec<_>>(),
+        store.roots().collect::<Vec<_>>()
+    );
+    assert_eq!(loaded.sources().collect::<Vec<_>>(), vec![photos.as_path()]);
+    let _ = std::fs::remove_file(fname);
+}
+
+#[cfg(feature = "serde-json")]
 #[test]
 fn hashstore_read_write() {
     use fgs::HashStore;
@@ -239,7 +1031,49 @@ fn hashstore_read_write() {
     let store_fs = HashStore::from_file(fname).unwrap_or(HashStore::default());
     assert_eq!(
         store_fs.find(&IHash::from_str("9")).unwrap(),
-        "./test/pokemon/nonexistant.png"
+        std::path::Path::new("./test/pokemon/nonexistant.png")
     );
     let _ = remove_file(fname);
 }
+
+#[cfg(any(feature = "serde-json", feature = "binary-store"))]
+#[test]
+fn hashstore_dirty_flag() {
+    use fgs::HashStore;
+    use ihash::IHash;
+    use std::fs::remove_file;
+
+    let fname = "./test/dirty.json";
+    let mut store = HashStore::default();
+    store.add_hash(&IHash::from_str("9"), "./test/pokemon/nonexistant.png");
+    assert!(store.is_dirty());
+    assert!(store.save().is_err());
+    store.to_file(fname).unwrap();
+    assert!(store.is_dirty());
+
+    let mut store_fs = HashStore::from_file(fname).unwrap();
+    assert!(!store_fs.is_dirty());
+    store_fs.remove_path("./test/pokemon/nonexistant.png");
+    assert!(store_fs.is_dirty());
+    store_fs.save().unwrap();
+    assert!(!store_fs.is_dirty());
+
+    store.save_as(fname).unwrap();
+    assert!(!store.is_dirty());
+    assert_eq!(store.path(), Some(std::path::Path::new(fname)));
+    let _ = remove_file(fname);
+}
+
+#[cfg(not(any(feature = "serde-json", feature = "binary-store")))]
+#[test]
+fn hashstore_failed_write_keeps_file() {
+    use fgs::HashStore;
+    use std::fs;
+
+    let fname = "./test/kept.json";
+    fs::write(fname, "[]").unwrap();
+    assert!(HashStore::new().to_file(fname).is_err());
+    assert_eq!(fs::read_to_string(fname).unwrap(), "[]");
+    assert!(!std::path::Path::new("./test/kept.json.tmp").exists());
+    let _ = fs::remove_file(fname);
+}
@@ -9,12 +9,15 @@ fn main() -> iced::Result {
     let image_bytes = include_bytes!("../icon.png");
     let icon = image::load_from_memory(image_bytes).unwrap().into_rgba8().to_vec();
 
+    let session = gui::Session::load();
     gui::Gui::run(Settings {
         window: window::Settings {
-            size: (1300, 800),
+            size: session.window_size,
             icon: Some(window::icon::Icon::from_rgba(icon, 128, 128).unwrap()),
             ..Default::default()
         },
-        ..Settings::default()
+        // Unsaved fingerprints are checked for before the window goes away
+        exit_on_close_request: false,
+        ..Settings::with_flags(session)
     })
 }
\ No newline at end of file
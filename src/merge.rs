@@ -0,0 +1,224 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Serializer};
+
+use crate::fgs::HashStore;
+use crate::ihash::IHash;
+
+/// Which side wins when both stores hold the same path with different hashes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    KeepOurs,
+    KeepTheirs,
+    KeepBoth,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct MergeSummary {
+    pub added: usize,
+    pub conflicts: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ChangedEntry {
+    #[serde(with = "crate::paths")]
+    pub path: PathBuf,
+    pub ours: Vec<IHash>,
+    pub theirs: Vec<IHash>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct StoreDiff {
+    #[serde(serialize_with = "serialize_entries")]
+    pub only_ours: Vec<(IHash, PathBuf)>,
+    #[serde(serialize_with = "serialize_entries")]
+    pub only_theirs: Vec<(IHash, PathBuf)>,
+    pub changed: Vec<ChangedEntry>,
+}
+
+impl StoreDiff {
+    pub fn is_empty(&self) -> bool {
+        self.only_ours.is_empty() && self.only_theirs.is_empty() && self.changed.is_empty()
+    }
+}
+
+// Same [hash, path] pairs as the store file
+fn serialize_entries<S: Serializer>(
+    entries: &[(IHash, PathBuf)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct Entry<'a>(&'a IHash, #[serde(with = "crate::paths")] &'a Path);
+
+    serializer.collect_seq(entries.iter().map(|(hash, path)| Entry(hash, path)))
+}
+
+// Hashes per path, in path order
+fn by_path(store: &HashStore) -> BTreeMap<&Path, Vec<IHash>> {
+    let mut paths: BTreeMap<&Path, Vec<IHash>> = BTreeMap::new();
+    for (hash, path) in store.iter() {
+        let hashes = paths.entry(path).or_default();
+        if !hashes.contains(hash) {
+            hashes.push(*hash);
+        }
+    }
+    paths
+}
+
+impl HashStore {
+    /// Adds the entries of `other` that this store does not have yet.
+    ///
+    /// Roots of `other` are taken over unless this store already has a root of that name,
+    /// and so are its source folders.
+    pub fn merge(&mut self, other: &HashStore, policy: ConflictPolicy) -> MergeSummary {
+        for (name, root) in other.roots() {
+            if !self.roots().any(|(ours, _)| ours == name) {
+                self.add_root(name, root);
+            }
+        }
+        for source in other.sources() {
+            self.add_source(source);
+        }
+        let ours: BTreeMap<PathBuf, Vec<IHash>> = by_path(self)
+            .into_iter()
+            .map(|(path, hashes)| (path.to_path_buf(), hashes))
+            .collect();
+        let mut summary = MergeSummary::default();
+        for (path, theirs) in by_path(other) {
+            let new: Vec<&IHash> = match ours.get(path) {
+                None => theirs.iter().collect(),
+                Some(hashes) if theirs.iter().all(|h| hashes.contains(h)) => continue,
+                Some(hashes) => {
+                    summary.conflicts += 1;
+                    match policy {
+                        ConflictPolicy::KeepOurs => continue,
+                        ConflictPolicy::KeepTheirs => {
+                            self.remove_path(path);
+                            theirs.iter().collect()
+                        }
+                        ConflictPolicy::KeepBoth => {
+                            theirs.iter().filter(|h| !hashes.contains(h)).collect()
+                        }
+                    }
+                }
+            };
+            for hash in new {
+                self.add_hash(hash, path);
+                summary.added += 1;
+            }
+        }
+        summary
+    }
+
+    /// Compares paths and their hashes, with this store as ours.
+    pub fn diff(&self, other: &HashStore) -> StoreDiff {
+        let ours = by_path(self);
+        let theirs = by_path(other);
+        let mut diff = StoreDiff::default();
+        for (path, hashes) in ours.iter() {
+            match theirs.get(path) {
+                None => {
+                    for hash in hashes {
+                        diff.only_ours.push((*hash, path.to_path_buf()));
+                    }
+                }
+                Some(other_hashes) => {
+                    let same = hashes.len() == other_hashes.len()
+                        && hashes.iter().all(|h| other_hashes.contains(h));
+                    if !same {
+                        diff.changed.push(ChangedEntry {
+                            path: path.to_path_buf(),
+                            ours: hashes.clone(),
+                            theirs: other_hashes.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        for (path, hashes) in theirs.iter() {
+            if !ours.contains_key(path) {
+                for hash in hashes {
+                    diff.only_theirs.push((*hash, path.to_path_buf()));
+                }
+            }
+        }
+        diff
+    }
+
+    /// Splits into the entries under `prefix` and everything else.
+    ///
+    /// The prefix is matched on whole path components, so `/photos` does not take `/photos2`.
+    pub fn split(&self, prefix: &Path) -> (HashStore, HashStore) {
+        let mut inside = HashStore::new();
+        let mut outside = HashStore::new();
+        for (name, root) in self.roots() {
+            inside.add_root(name, root);
+            outside.add_root(name, root);
+        }
+        for source in self.sources() {
+            if source.starts_with(prefix) {
+                inside.add_source(source);
+            } else {
+                outside.add_source(source);
+            }
+        }
+        for (hash, path) in self.iter() {
+            if path.starts_with(prefix) {
+                inside.add_hash(hash, path);
+            } else {
+                outside.add_hash(hash, path);
+            }
+        }
+        (inside, outside)
+    }
+}
+
+#[test]
+fn merge_diff_split() {
+    let hash = |s: &str| IHash::from_str(s);
+    let mut ours = HashStore::new();
+    ours.add_hash(&hash("1"), "/share/a.png");
+    ours.add_hash(&hash("2"), "/share/b.png");
+    ours.add_hash(&hash("3"), "/other/c.png");
+    let mut theirs = HashStore::new();
+    theirs.add_hash(&hash("1"), "/share/a.png");
+    theirs.add_hash(&hash("9"), "/share/b.png");
+    theirs.add_hash(&hash("4"), "/share2/d.png");
+
+    let diff = ours.diff(&theirs);
+    assert_eq!(
+        diff.only_ours,
+        vec![(hash("3"), PathBuf::from("/other/c.png"))]
+    );
+    assert_eq!(
+        diff.only_theirs,
+        vec![(hash("4"), PathBuf::from("/share2/d.png"))]
+    );
+    assert_eq!(diff.changed.len(), 1);
+    assert_eq!(diff.changed[0].path, Path::new("/share/b.png"));
+
+    let mut kept = ours.clone();
+    let summary = kept.merge(&theirs, ConflictPolicy::KeepOurs);
+    assert_eq!(
+        summary,
+        MergeSummary {
+            added: 1,
+            conflicts: 1
+        }
+    );
+    assert_eq!(kept.find(&hash("2")), Some(Path::new("/share/b.png")));
+
+    let mut replaced = ours.clone();
+    replaced.merge(&theirs, ConflictPolicy::KeepTheirs);
+    assert_eq!(replaced.len(), 4);
+    assert!(replaced.diff(&theirs).changed.is_empty());
+
+    let mut both = ours.clone();
+    both.merge(&theirs, ConflictPolicy::KeepBoth);
+    assert_eq!(both.len(), 5);
+
+    let (share, rest) = both.split(Path::new("/share"));
+    assert_eq!(share.len(), 3);
+    assert_eq!(rest.len(), 2);
+}
@@ -0,0 +1,186 @@
+//! Serde support for paths that are not valid UTF-8.
+//!
+//! Valid UTF-8 paths are written as plain strings, so files without odd names read the same as
+//! before. Anything else keeps its raw OS encoding, bytes on Unix and UTF-16 on Windows. A path
+//! written on the other platform is converted lossily when read back.
+//!
+//! Binary formats such as the `binary-store` files cannot tell untagged variants apart, so
+//! there every path carries its variant.
+//!
+//! Use as `#[serde(with = "crate::paths")]`, or `crate::paths::many` for lists of paths.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Encoded {
+    Utf8(String),
+    Unix { unix: Vec<u8> },
+    Windows { windows: Vec<u16> },
+}
+
+#[derive(Serialize, Deserialize)]
+enum Tagged {
+    Utf8(String),
+    Unix(Vec<u8>),
+    Windows(Vec<u16>),
+}
+
+impl From<Encoded> for Tagged {
+    fn from(encoded: Encoded) -> Self {
+        match encoded {
+            Encoded::Utf8(path) => Tagged::Utf8(path),
+            Encoded::Unix { unix } => Tagged::Unix(unix),
+            Encoded::Windows { windows } => Tagged::Windows(windows),
+        }
+    }
+}
+
+impl From<Tagged> for Encoded {
+    fn from(tagged: Tagged) -> Self {
+        match tagged {
+            Tagged::Utf8(path) => Encoded::Utf8(path),
+            Tagged::Unix(unix) => Encoded::Unix { unix },
+            Tagged::Windows(windows) => Encoded::Windows { windows },
+        }
+    }
+}
+
+impl Encoded {
+    fn new(path: &Path) -> Self {
+        match path.to_str() {
+            Some(path) => Encoded::Utf8(path.to_string()),
+            None => native(path),
+        }
+    }
+
+    fn into_path(self) -> PathBuf {
+        match self {
+            Encoded::Utf8(path) => PathBuf::from(path),
+            Encoded::Unix { unix } => from_unix(unix),
+            Encoded::Windows { windows } => from_windows(windows),
+        }
+    }
+
+    fn write<S: Serializer>(self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            self.serialize(serializer)
+        } else {
+            Tagged::from(self).serialize(serializer)
+        }
+    }
+
+    fn read<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            Encoded::deserialize(deserializer)
+        } else {
+            Tagged::deserialize(deserializer).map(Encoded::from)
+        }
+    }
+}
+
+#[cfg(unix)]
+fn native(path: &Path) -> Encoded {
+    use std::os::unix::ffi::OsStrExt;
+    Encoded::Unix {
+        unix: path.as_os_str().as_bytes().to_vec(),
+    }
+}
+
+#[cfg(windows)]
+fn native(path: &Path) -> Encoded {
+    use std::os::windows::ffi::OsStrExt;
+    Encoded::Windows {
+        windows: path.as_os_str().encode_wide().collect(),
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn native(path: &Path) -> Encoded {
+    Encoded::Utf8(path.to_string_lossy().into_owned())
+}
+
+#[cfg(unix)]
+fn from_unix(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(bytes).into()
+}
+
+#[cfg(not(unix))]
+fn from_unix(bytes: Vec<u8>) -> PathBuf {
+    OsString::from(String::from_utf8_lossy(&bytes).into_owned()).into()
+}
+
+#[cfg(windows)]
+fn from_windows(wide: Vec<u16>) -> PathBuf {
+    use std::os::windows::ffi::OsStringExt;
+    OsString::from_wide(&wide).into()
+}
+
+#[cfg(not(windows))]
+fn from_windows(wide: Vec<u16>) -> PathBuf {
+    OsString::from(String::from_utf16_lossy(&wide)).into()
+}
+
+pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    Encoded::new(path).write(serializer)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+    Encoded::read(deserializer).map(Encoded::into_path)
+}
+
+pub mod many {
+    use std::path::{Path, PathBuf};
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    // Each element is written the same way a single path is
+    struct Borrowed<'a>(&'a Path);
+
+    impl Serialize for Borrowed<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            super::serialize(self.0, serializer)
+        }
+    }
+
+    struct Owned(PathBuf);
+
+    impl<'de> Deserialize<'de> for Owned {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            super::deserialize(deserializer).map(Owned)
+        }
+    }
+
+    pub fn serialize<S: Serializer>(paths: &[PathBuf], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(paths.iter().map(|path| Borrowed(path)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<PathBuf>, D::Error> {
+        let paths: Vec<Owned> = Vec::deserialize(deserializer)?;
+        Ok(paths.into_iter().map(|Owned(path)| path).collect())
+    }
+}
+
+#[cfg(all(unix, feature = "serde-json"))]
+#[test]
+fn non_utf8_round_trip() {
+    use std::os::unix::ffi::OsStrExt;
+
+    #[derive(Serialize, Deserialize)]
+    struct Wrapper(#[serde(with = "crate::paths")] PathBuf);
+
+    let odd = PathBuf::from(std::ffi::OsStr::from_bytes(b"/photos/caf\xe9.png"));
+    let json = serde_json::to_string(&Wrapper(odd.clone())).unwrap();
+    assert!(json.contains("unix"));
+    let read: Wrapper = serde_json::from_str(&json).unwrap();
+    assert_eq!(read.0, odd);
+
+    let plain = serde_json::to_string(&Wrapper(PathBuf::from("/photos/a.png"))).unwrap();
+    assert_eq!(plain, r#""/photos/a.png""#);
+}
@@ -0,0 +1,262 @@
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use image::{DynamicImage, ImageResult};
+
+use crate::animation::{self, FrameSampling};
+use crate::ihash::{dhash, dhash_bytes, dhash_raw, IHash, PixelFormat};
+
+pub type HashPair = (IHash, PathBuf);
+
+/// Extensions of the files the image crate is built to decode.
+pub const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "bmp", "gif", "webp", "tiff", "ico"];
+
+pub fn is_image(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        IMAGE_EXTENSIONS
+            .iter()
+            .any(|known| ext.eq_ignore_ascii_case(known))
+    })
+}
+
+/// Decodes the image at a stored path, which may be a member of an archive or a frame of a video
+/// or animation.
+pub fn open_image(path: &Path) -> ImageResult<DynamicImage> {
+    if animation::split_frame(path).is_some() {
+        return animation::read_frame(path);
+    }
+    #[cfg(feature = "archive")]
+    if crate::archive::split_member(path).is_some() {
+        return image::load_from_memory(&crate::archive::read_member(path)?);
+    }
+    #[cfg(all(feature = "video", not(target_arch = "wasm32")))]
+    if crate::video::split_frame(path).is_some() {
+        return Ok(crate::video::read_frame(path)?);
+    }
+    image::open(path)
+}
+
+/// The file on disk a stored path lives in, which is the archive itself for archive members
+/// and the video or animation for their frames.
+pub fn on_disk(path: &Path) -> &Path {
+    if let Some((file, _)) = animation::split_frame(path) {
+        return file;
+    }
+    #[cfg(feature = "archive")]
+    if let Some((archive, _)) = crate::archive::split_member(path) {
+        return archive;
+    }
+    #[cfg(all(feature = "video", not(target_arch = "wasm32")))]
+    if let Some((video, _)) = crate::video::split_frame(path) {
+        return video;
+    }
+    path
+}
+
+/// Every way of cutting a stored path in two around `separator`, first match first.
+///
+/// Works on the raw bytes of the path, so the file part comes back exactly as it was even when
+/// it is not valid UTF-8.
+pub(crate) fn split_around<'a>(
+    path: &'a Path,
+    separator: &'a str,
+) -> impl DoubleEndedIterator<Item = (&'a Path, &'a OsStr)> + 'a {
+    let bytes = path.as_os_str().as_encoded_bytes();
+    let separator = separator.as_bytes();
+    (0..=bytes.len().saturating_sub(separator.len()))
+        .filter(move |&at| bytes[at..].starts_with(separator))
+        .map(move |at| {
+            let (head, tail) = (&bytes[..at], &bytes[at + separator.len()..]);
+            // SAFETY: both halves come from an OsStr and border on a non-empty UTF-8 separator
+            unsafe {
+                (
+                    Path::new(OsStr::from_encoded_bytes_unchecked(head)),
+                    OsStr::from_encoded_bytes_unchecked(tail),
+                )
+            }
+        })
+}
+
+/// The hash a file is stored under, or None when it cannot be decoded.
+pub fn hash_file(path: &Path) -> Option<IHash> {
+    open_image(path).ok().map(|image| dhash(&image))
+}
+
+/// Where the pixels of an image come from when they are not simply a file on disk.
+#[derive(Debug, Clone)]
+pub enum HashSource {
+    File(PathBuf),
+    /// The contents of an image file, already read from a download, a database or the like.
+    Encoded(Vec<u8>),
+    /// Decoded pixels, with rows starting `stride` bytes apart.
+    Raw {
+        pixels: Vec<u8>,
+        width: u32,
+        height: u32,
+        stride: usize,
+        format: PixelFormat,
+    },
+}
+
+impl HashSource {
+    /// The hash the image is stored under, or None when it cannot be decoded.
+    pub fn hash(&self) -> Option<IHash> {
+        match self {
+            HashSource::File(path) => hash_file(path),
+            HashSource::Encoded(bytes) => dhash_bytes(bytes).ok(),
+            HashSource::Raw {
+                pixels,
+                width,
+                height,
+                stride,
+                format,
+            } => dhash_raw(pixels, *width, *height, *stride, *format).ok(),
+        }
+    }
+}
+
+/// Hashes the sources on a background thread, like [`hash_files`].
+///
+/// Each result is sent with the path it was paired with, which is what the store records.
+pub fn hash_sources<I>(sources: I) -> Receiver<HashPair>
+where
+    I: IntoIterator<Item = (PathBuf, HashSource)> + Send + 'static,
+    I::IntoIter: Send,
+{
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        for (path, source) in sources {
+            if !send_hash(&tx, path, source) {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+// False once nobody is listening anymore
+fn send_hash(tx: &Sender<HashPair>, path: PathBuf, source: HashSource) -> bool {
+    match source.hash() {
+        Some(hash) => tx.send((hash, path)).is_ok(),
+        None => true,
+    }
+}
+
+// Archives and animations stand for every image inside them, which are read one at a time as
+// hashing gets to them. False once `send` asked to stop.
+fn for_each_source<F>(path: PathBuf, frames: FrameSampling, mut send: F) -> bool
+where
+    F: FnMut(PathBuf, HashSource) -> bool,
+{
+    #[cfg(feature = "archive")]
+    if crate::archive::is_archive(&path) {
+        let mut going = true;
+        let _ = crate::archive::for_each_source(&path, |member, source| {
+            going = send(member, source);
+            going
+        });
+        return going;
+    }
+    if frames != FrameSampling::First && animation::is_animated(&path) {
+        let mut going = true;
+        let _ = animation::for_each_source(&path, frames, |frame, source| {
+            going = send(frame, source);
+            going
+        });
+        return going;
+    }
+    send(path.clone(), HashSource::File(path))
+}
+
+/// Hashes the files on a background thread, sending each result as soon as it is ready.
+///
+/// Files that cannot be decoded are skipped. The receiver disconnects once every file was tried.
+/// Images inside archives are sent with the path of their member, see [`crate::archive`].
+pub fn hash_files(paths: Vec<PathBuf>) -> Receiver<HashPair> {
+    hash_files_with(paths, FrameSampling::First)
+}
+
+/// Like [`hash_files`], with the frames of animated images picked by `frames`.
+///
+/// Frames are sent with the paths from [`animation::frame_path`].
+pub fn hash_files_with(paths: Vec<PathBuf>, frames: FrameSampling) -> Receiver<HashPair> {
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        for path in paths {
+            if !for_each_source(path, frames, |path, source| send_hash(&tx, path, source)) {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+#[test]
+fn hash_sources_agree() {
+    let path = PathBuf::from("./test/pokemon/bulbasaur.png");
+    let image = image::open(&path).unwrap();
+    let expected = dhash(&image);
+    let luma = image.to_luma8();
+    let (width, height) = luma.dimensions();
+    let sources = vec![
+        (path.clone(), HashSource::File(path.clone())),
+        (
+            PathBuf::from("download"),
+            HashSource::Encoded(std::fs::read(&path).unwrap()),
+        ),
+        (PathBuf::from("broken"), HashSource::Encoded(vec![1, 2, 3])),
+        (
+            PathBuf::from("frame"),
+            HashSource::Raw {
+                pixels: luma.into_raw(),
+                width,
+                height,
+                stride: width as usize,
+                format: PixelFormat::Luma8,
+            },
+        ),
+    ];
+    let results: Vec<HashPair> = hash_sources(sources).iter().collect();
+    let paths: Vec<&str> = results.iter().map(|(_, p)| p.to_str().unwrap()).collect();
+    assert_eq!(paths, [path.to_str().unwrap(), "download", "frame"]);
+    assert!(results.iter().all(|(hash, _)| *hash == expected));
+}
+
+#[cfg(unix)]
+#[test]
+fn split_non_utf8_paths() {
+    use std::os::unix::ffi::OsStrExt;
+
+    let file = |name: &[u8]| PathBuf::from(OsStr::from_bytes(name));
+    let path = file(b"a\xff!/b!/c");
+    let split: Vec<_> = split_around(&path, "!/").collect();
+    assert_eq!(
+        split,
+        [
+            (file(b"a\xff").as_path(), OsStr::new("b!/c")),
+            (file(b"a\xff!/b").as_path(), OsStr::new("c")),
+        ]
+    );
+    assert_eq!(split_around(&file(b"!"), "!/").count(), 0);
+
+    let gif = file(b"reactions/\xe9t\xe9.gif");
+    let frame = animation::frame_path(&gif, 3);
+    assert_eq!(animation::split_frame(&frame), Some((gif.as_path(), 3)));
+
+    #[cfg(feature = "archive")]
+    {
+        let archive = file(b"comics/\xe9t\xe9.cbz");
+        let member = crate::archive::member_path(&archive, "001.png");
+        assert_eq!(on_disk(&member), archive);
+    }
+    #[cfg(all(feature = "video", not(target_arch = "wasm32")))]
+    {
+        let video = file(b"films/\xe9t\xe9.mp4");
+        let frame = crate::video::frame_path(&video, 12.5);
+        assert_eq!(
+            crate::video::split_frame(&frame),
+            Some((video.as_path(), 12.5))
+        );
+    }
+}
@@ -0,0 +1,257 @@
+//! A small JSON API over a [`HashStore`], for tools that cannot link against this crate.
+//!
+//! Hashes travel as decimal strings, the way the CLI prints them.
+//!
+//! - `POST /hash` with an image as the body returns `{"hash": "..."}`.
+//! - `POST /search?top=N` with an image, or with `{"hash": "..."}` as JSON, returns
+//!   `{"matches": [{"path": "...", "distance": 0}]}`, closest first.
+//! - `POST /entries` with `{"path": "...", "hash": "..."}` stores an entry. Without a hash the
+//!   file is hashed from disk.
+//! - `DELETE /entries` with `{"path": "..."}` drops every entry for the path.
+//!
+//! Failures come back as `{"error": "..."}` with a 4xx or 5xx status. Changes are saved as
+//! they happen when the store was opened from a file.
+
+use std::io::Error;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response};
+
+use crate::fgs::HashStore;
+use crate::ihash::{dhash, HashMethod, IHash};
+use crate::pipeline::hash_file;
+
+/// Matches returned by `/search` when the query does not ask for a number.
+pub const DEFAULT_TOP: usize = 5;
+
+#[derive(Deserialize)]
+struct HashQuery {
+    hash: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EntryBody {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
+    #[serde(with = "crate::paths")]
+    path: PathBuf,
+}
+
+// Status code and message of a failed request
+type Failure = (u16, String);
+
+pub struct Server {
+    http: tiny_http::Server,
+    store: HashStore,
+}
+
+impl Server {
+    /// Listens on 127.0.0.1 only. Port 0 picks any free port, see [`Server::addr`].
+    pub fn bind(store: HashStore, port: u16) -> Result<Self, Error> {
+        let http = tiny_http::Server::http(("127.0.0.1", port)).map_err(Error::other)?;
+        Ok(Server { http, store })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.http
+            .server_addr()
+            .to_ip()
+            .expect("bound to a TCP address")
+    }
+
+    pub fn store(&self) -> &HashStore {
+        &self.store
+    }
+
+    /// Answers requests one at a time until the listener shuts down.
+    pub fn run(&mut self) {
+        while let Ok(mut request) = self.http.recv() {
+            let (status, body) = self
+                .handle(&mut request)
+                .unwrap_or_else(|(status, error)| (status, json!({ "error": error })));
+            let content_type: Header = "Content-Type: application/json".parse().unwrap();
+            let response = Response::from_string(body.to_string())
+                .with_status_code(status)
+                .with_header(content_type);
+            // The client hanging up early is its own problem
+            let _ = request.respond(response);
+        }
+    }
+
+    fn handle(&mut self, request: &mut Request) -> Result<(u16, Value), Failure> {
+        let mut body = vec![];
+        request
+            .as_reader()
+            .read_to_end(&mut body)
+            .map_err(|e| (400, e.to_string()))?;
+        let url = request.url().to_string();
+        let (route, query) = url.split_once('?').unwrap_or((&url, ""));
+
+        match (request.method(), route) {
+            (Method::Post, "/hash") => {
+                let image = decode_image(&body)?;
+                Ok((200, json!({ "hash": dhash(&image).to_str() })))
+            }
+            (Method::Post, "/search") => {
+                let top = match query.split('&').find_map(|p| p.strip_prefix("top=")) {
+                    Some(top) => top
+                        .parse()
+                        .map_err(|_| (400, format!("invalid top: {}", top)))?,
+                    None => DEFAULT_TOP,
+                };
+                let hashes = if is_json(request) {
+                    let query: HashQuery = parse_json(&body)?;
+                    vec![parse_hash(&query.hash)?]
+                } else {
+                    HashMethod::default().hashes(&decode_image(&body)?)
+                };
+                let matches = self.store.find_matches(&hashes, top);
+                Ok((200, json!({ "matches": matches })))
+            }
+            (Method::Post, "/entries") => {
+                let entry: EntryBody = parse_json(&body)?;
+                let hash = match &entry.hash {
+                    Some(hash) => parse_hash(hash)?,
+                    None => hash_file(&entry.path)
+                        .ok_or_else(|| (400, format!("could not hash {}", entry.path.display())))?,
+                };
+                let known = self
+                    .store
+                    .iter()
+                    .any(|(h, p)| *h == hash && p == entry.path);
+                if !known {
+                    self.store.add_hash(&hash, &entry.path);
+                    self.persist()?;
+                }
+                let stored = EntryBody {
+                    hash: Some(hash.to_str()),
+                    path: entry.path,
+                };
+                Ok((if known { 200 } else { 201 }, json!(stored)))
+            }
+            (Method::Delete, "/entries") => {
+                let entry: EntryBody = parse_json(&body)?;
+                let removed = self.store.remove_path(&entry.path).len();
+                if removed == 0 {
+                    return Err((404, format!("no entries for {}", entry.path.display())));
+                }
+                self.persist()?;
+                Ok((200, json!({ "removed": removed })))
+            }
+            (_, "/hash" | "/search" | "/entries") => Err((
+                405,
+                format!("{} not allowed on {}", request.method(), route),
+            )),
+            _ => Err((404, format!("no such endpoint: {}", route))),
+        }
+    }
+
+    fn persist(&mut self) -> Result<(), Failure> {
+        if self.store.path().is_some() {
+            self.store
+                .save()
+                .map_err(|e| (500, format!("could not save: {}", e)))?;
+        }
+        Ok(())
+    }
+}
+
+fn is_json(request: &Request) -> bool {
+    request.headers().iter().any(|header| {
+        header.field.equiv("Content-Type") && header.value.as_str().starts_with("application/json")
+    })
+}
+
+fn decode_image(body: &[u8]) -> Result<image::DynamicImage, Failure> {
+    image::load_from_memory(body).map_err(|e| (400, format!("not an image: {}", e)))
+}
+
+fn parse_json<'a, T: Deserialize<'a>>(body: &'a [u8]) -> Result<T, Failure> {
+    serde_json::from_slice(body).map_err(|e| (400, format!("invalid request: {}", e)))
+}
+
+fn parse_hash(hash: &str) -> Result<IHash, Failure> {
+    hash.parse()
+        .map(IHash::new)
+        .map_err(|_| (400, format!("invalid hash: {}", hash)))
+}
+
+#[test]
+fn serve_over_http() {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::path::Path;
+
+    fn send(addr: SocketAddr, request: &str, content_type: &str, body: &[u8]) -> (u16, Value) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "{} HTTP/1.1\r\nHost: localhost\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            request,
+            content_type,
+            body.len()
+        )
+        .unwrap();
+        stream.write_all(body).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    let bulbasaur = Path::new("./test/pokemon/bulbasaur.png");
+    let ivysaur = Path::new("./test/pokemon/ivysaur.png");
+    let mut store = HashStore::new();
+    for path in [bulbasaur, ivysaur] {
+        store.add_hash(&hash_file(path).unwrap(), path);
+    }
+    let mut server = Server::bind(store, 0).unwrap();
+    let addr = server.addr();
+    std::thread::spawn(move || server.run());
+
+    let image = std::fs::read(bulbasaur).unwrap();
+    let hash = hash_file(bulbasaur).unwrap().to_str();
+    let (status, body) = send(addr, "POST /hash", "image/png", &image);
+    assert_eq!(status, 200);
+    assert_eq!(body["hash"], hash);
+
+    let (status, body) = send(addr, "POST /search?top=1", "image/png", &image);
+    assert_eq!(status, 200);
+    assert_eq!(body["matches"][0]["path"], "./test/pokemon/bulbasaur.png");
+    assert_eq!(body["matches"][0]["distance"], 0);
+
+    let copy = json!({ "hash": hash, "path": "/elsewhere/copy.png" }).to_string();
+    let (status, _) = send(addr, "POST /entries", "application/json", copy.as_bytes());
+    assert_eq!(status, 201);
+    let query = json!({ "hash": hash }).to_string();
+    let (_, body) = send(
+        addr,
+        "POST /search?top=3",
+        "application/json",
+        query.as_bytes(),
+    );
+    let distances: Vec<&Value> = body["matches"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| &m["distance"])
+        .collect();
+    assert_eq!(distances[..2], [0, 0]);
+
+    let copy = json!({ "path": "/elsewhere/copy.png" }).to_string();
+    let (status, body) = send(addr, "DELETE /entries", "application/json", copy.as_bytes());
+    assert_eq!((status, body), (200, json!({ "removed": 1 })));
+    let (status, _) = send(addr, "DELETE /entries", "application/json", copy.as_bytes());
+    assert_eq!(status, 404);
+
+    let bad = json!({ "hash": "not a number" }).to_string();
+    assert_eq!(
+        send(addr, "POST /search", "application/json", bad.as_bytes()).0,
+        400
+    );
+    assert_eq!(send(addr, "GET /search", "text/plain", b"").0, 405);
+}
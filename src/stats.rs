@@ -0,0 +1,183 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::fgs::HashStore;
+use crate::ihash::{dhash, IHash};
+use crate::pipeline::{on_disk, open_image};
+
+// IHash::comp also checks the mirrored hash, so no two hashes are more than 32 bits apart
+const MAX_DISTANCE: usize = 32;
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct StoreStats {
+    pub entries: usize,
+    pub unique_paths: usize,
+    /// Paths stored more than once
+    #[serde(with = "crate::paths::many")]
+    pub duplicate_paths: Vec<PathBuf>,
+    /// `nearest_neighbours[d]` entries have their closest other entry `d` bits away
+    pub nearest_neighbours: Vec<usize>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ChangedFile {
+    #[serde(with = "crate::paths")]
+    pub path: PathBuf,
+    pub stored: IHash,
+    pub current: IHash,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct VerifyReport {
+    pub checked: usize,
+    #[serde(with = "crate::paths::many")]
+    pub missing: Vec<PathBuf>,
+    /// Files that exist but could not be decoded
+    #[serde(with = "crate::paths::many")]
+    pub unreadable: Vec<PathBuf>,
+    pub changed: Vec<ChangedFile>,
+}
+
+impl VerifyReport {
+    pub fn is_healthy(&self) -> bool {
+        self.missing.is_empty() && self.unreadable.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl HashStore {
+    pub fn stats(&self) -> StoreStats {
+        let mut counts: HashMap<&Path, usize> = HashMap::new();
+        for (_, path) in self.iter() {
+            *counts.entry(path).or_insert(0) += 1;
+        }
+        let mut duplicate_paths: Vec<PathBuf> = counts
+            .iter()
+            .filter(|(_, count)| **count > 1)
+            .map(|(path, _)| path.to_path_buf())
+            .collect();
+        duplicate_paths.sort();
+
+        // Entries sharing a hash are each other's nearest neighbours, so only the distinct
+        // hashes have to be compared with one another
+        let mut copies: HashMap<&IHash, usize> = HashMap::new();
+        for (hash, _) in self.iter() {
+            *copies.entry(hash).or_insert(0) += 1;
+        }
+        let distinct: Vec<&IHash> = copies.keys().copied().collect();
+        let mut nearest_neighbours = vec![0; MAX_DISTANCE + 1];
+        nearest_neighbours[0] = copies.values().filter(|count| **count > 1).sum();
+        for (i, hash) in distinct.iter().enumerate() {
+            if copies[hash] > 1 {
+                continue;
+            }
+            let mut nearest = None;
+            for (j, other) in distinct.iter().enumerate() {
+                let distance = hash.dist(other);
+                if j == i || nearest.is_some_and(|n| distance >= n) {
+                    continue;
+                }
+                nearest = Some(distance);
+                // Mirrored hashes are 0 bits apart, nothing gets closer
+                if distance == 0 {
+                    break;
+                }
+            }
+            if let Some(distance) = nearest {
+                nearest_neighbours[distance as usize] += 1;
+            }
+        }
+
+        StoreStats {
+            entries: self.len(),
+            unique_paths: counts.len(),
+            duplicate_paths,
+            nearest_neighbours,
+        }
+    }
+
+    /// Hashes every stored file again and reports the ones that are gone or no longer match.
+    pub fn verify(&self) -> VerifyReport {
+        let mut report = VerifyReport::default();
+        let mut stored_by_path: HashMap<&Path, Vec<&IHash>> = HashMap::new();
+        for (hash, path) in self.iter() {
+            stored_by_path.entry(path).or_default().push(hash);
+        }
+        let mut seen: HashSet<&Path> = HashSet::new();
+        for (stored, path) in self.iter() {
+            if !seen.insert(path) {
+                continue;
+            }
+            report.checked += 1;
+            if !on_disk(path).exists() {
+                report.missing.push(path.to_path_buf());
+                continue;
+            }
+            let current = match open_image(path) {
+                Ok(image) => dhash(&image),
+                Err(_) => {
+                    report.unreadable.push(path.to_path_buf());
+                    continue;
+                }
+            };
+            let matches_any = stored_by_path[path].iter().any(|h| h.dist(&current) == 0);
+            if !matches_any {
+                report.changed.push(ChangedFile {
+                    path: path.to_path_buf(),
+                    stored: *stored,
+                    current,
+                });
+            }
+        }
+        report
+    }
+}
+
+#[test]
+fn stats_and_verify() {
+    let bulbasaur = "./test/pokemon/bulbasaur.png";
+    let ivysaur = "./test/pokemon/ivysaur.png";
+    let hash = dhash(&image::open(bulbasaur).unwrap());
+
+    let mut store = HashStore::new();
+    store.add_hash(&hash, bulbasaur);
+    store.add_hash(&hash, bulbasaur);
+    store.add_hash(&hash, ivysaur);
+    store.add_hash(&hash, "./test/pokemon/nonexistant.png");
+
+    let stats = store.stats();
+    assert_eq!(stats.entries, 4);
+    assert_eq!(stats.unique_paths, 3);
+    assert_eq!(stats.duplicate_paths, vec![PathBuf::from(bulbasaur)]);
+    assert_eq!(stats.nearest_neighbours[0], 4);
+
+    let report = store.verify();
+    assert_eq!(report.checked, 3);
+    assert_eq!(
+        report.missing,
+        vec![PathBuf::from("./test/pokemon/nonexistant.png")]
+    );
+    assert_eq!(report.changed.len(), 1);
+    assert_eq!(report.changed[0].path, Path::new(ivysaur));
+    assert!(!report.is_healthy());
+}
+
+#[test]
+fn nearest_neighbours() {
+    let mut store = HashStore::new();
+    for (value, path) in [
+        (0b0, "a"),
+        (0b0, "b"),
+        (0b1, "c"),
+        (0b111, "d"),
+        (!0b111, "e"),
+    ] {
+        store.add_hash(&IHash::new(value), path);
+    }
+    let stats = store.stats();
+    // a and b share a hash, c is 1 bit from them, d and e mirror each other
+    assert_eq!(stats.nearest_neighbours[0], 4);
+    assert_eq!(stats.nearest_neighbours[1], 1);
+    assert_eq!(stats.nearest_neighbours.iter().sum::<usize>(), 5);
+}
@@ -0,0 +1,338 @@
+//! Frames sampled out of video files, decoded by the `ffmpeg` command line tool.
+//!
+//! Each frame is stored under the path of the video followed by `#t=` and its time in seconds,
+//! like `films/intro.mp4#t=12.5`, the media fragment syntax browsers understand as well.
+//!
+//! Frames only get into a store through `fingerprint-cli videos`. Directory hashing in the app
+//! and [`crate::pipeline::hash_files`] skip videos, as sampling them takes far longer than an
+//! image and needs `ffmpeg`. Frames already in a store are opened like any other entry.
+
+use std::ffi::OsString;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+use image::{DynamicImage, RgbImage};
+
+use crate::ihash::{dhash, IHash};
+use crate::pipeline::{split_around, HashPair};
+
+pub const VIDEO_EXTENSIONS: &[&str] = &[
+    "mp4", "m4v", "mkv", "webm", "mov", "avi", "mpg", "mpeg", "wmv",
+];
+
+const SEPARATOR: &str = "#t=";
+
+// Frames per second looked at when searching for scene changes
+const SCENE_RATE: f64 = 4.0;
+
+/// Which frames of a video end up in the store.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sampling {
+    /// One frame every this many seconds, starting with the first
+    Interval(f64),
+    /// The first frame, then every frame at least this many bits away from the last one kept
+    SceneChange(u32),
+}
+
+impl Sampling {
+    /// Frames per second that have to be decoded to sample this way.
+    pub fn rate(self) -> f64 {
+        match self {
+            Sampling::Interval(seconds) => 1.0 / seconds,
+            Sampling::SceneChange(_) => SCENE_RATE,
+        }
+    }
+}
+
+pub fn is_video(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        VIDEO_EXTENSIONS
+            .iter()
+            .any(|known| ext.eq_ignore_ascii_case(known))
+    })
+}
+
+/// The path the frame at `seconds` into the video is stored under, to the millisecond.
+pub fn frame_path(video: &Path, seconds: f64) -> PathBuf {
+    let seconds = (seconds * 1000.0).round() / 1000.0;
+    let mut path = OsString::from(video);
+    path.push(format!("{}{}", SEPARATOR, seconds));
+    path.into()
+}
+
+/// Splits a path made by [`frame_path`] back into the video and the time of the frame.
+pub fn split_frame(path: &Path) -> Option<(&Path, f64)> {
+    let (video, seconds) = split_around(path, SEPARATOR).next_back()?;
+    let seconds: f64 = seconds.to_str()?.parse().ok()?;
+    (is_video(video) && seconds >= 0.0).then_some((video, seconds))
+}
+
+/// Picks the frames to store out of `(seconds, hash)` pairs in playing order.
+pub fn keyframes<I>(frames: I, sampling: Sampling) -> Vec<(f64, IHash)>
+where
+    I: IntoIterator<Item = (f64, IHash)>,
+{
+    let mut kept: Vec<(f64, IHash)> = vec![];
+    for (seconds, hash) in frames {
+        let keep = match (sampling, kept.last()) {
+            (_, None) => true,
+            // The first frame to start a new interval, frame times are not exact
+            (Sampling::Interval(interval), Some((last, _))) => {
+                let slot = |seconds: f64| (seconds / interval + 1e-6).floor();
+                slot(seconds) > slot(*last)
+            }
+            (Sampling::SceneChange(bits), Some((_, last))) => hash.dist(last) >= bits,
+        };
+        if keep {
+            kept.push((seconds, hash));
+        }
+    }
+    kept
+}
+
+/// Decoded frames of a video at a fixed rate, with their time in seconds.
+///
+/// Dropping it stops `ffmpeg`.
+pub struct Frames {
+    child: Child,
+    stdout: BufReader<ChildStdout>,
+    rate: f64,
+    index: usize,
+}
+
+impl Frames {
+    /// Starts decoding `rate` frames per second of the video.
+    pub fn decode(video: &Path, rate: f64) -> Result<Self, Error> {
+        let mut child = ffmpeg()
+            .arg("-i")
+            .arg(video)
+            .args(["-vf", &format!("fps={}", rate)])
+            .args(["-f", "image2pipe", "-c:v", "ppm", "-"])
+            .spawn()?;
+        let stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+        Ok(Frames {
+            child,
+            stdout,
+            rate,
+            index: 0,
+        })
+    }
+
+    // Called once the frames run out, which is also when a broken file is noticed
+    fn finish(&mut self) -> Option<Result<(f64, DynamicImage), Error>> {
+        match self.child.wait() {
+            Ok(status) if status.success() => None,
+            Ok(status) => Some(Err(Error::other(format!("ffmpeg failed with {}", status)))),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+impl Iterator for Frames {
+    type Item = Result<(f64, DynamicImage), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match read_ppm(&mut self.stdout) {
+            Ok(Some(image)) => {
+                let seconds = self.index as f64 / self.rate;
+                self.index += 1;
+                Some(Ok((seconds, DynamicImage::ImageRgb8(image))))
+            }
+            Ok(None) => self.finish(),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+impl Drop for Frames {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Samples the video and hashes the frames that were picked, paired with their [`frame_path`].
+///
+/// Intervals have to be a positive, finite number of seconds.
+pub fn hash_video(video: &Path, sampling: Sampling) -> Result<Vec<HashPair>, Error> {
+    if let Sampling::Interval(seconds) = sampling {
+        if !(seconds.is_finite() && seconds > 0.0) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid interval of {} seconds", seconds),
+            ));
+        }
+    }
+    let mut hashed = vec![];
+    for frame in Frames::decode(video, sampling.rate())? {
+        let (seconds, image) = frame?;
+        hashed.push((seconds, dhash(&image)));
+    }
+    Ok(keyframes(hashed, sampling)
+        .into_iter()
+        .map(|(seconds, hash)| (hash, frame_path(video, seconds)))
+        .collect())
+}
+
+/// Decodes the single frame a path made by [`frame_path`] points to.
+pub fn read_frame(path: &Path) -> Result<DynamicImage, Error> {
+    let (video, seconds) = split_frame(path)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "not a video frame"))?;
+    let output = ffmpeg()
+        .args(["-ss", &seconds.to_string(), "-i"])
+        .arg(video)
+        .args(["-frames:v", "1", "-f", "image2pipe", "-c:v", "ppm", "-"])
+        .output()?;
+    if !output.status.success() {
+        return Err(Error::other(format!(
+            "ffmpeg failed with {}",
+            output.status
+        )));
+    }
+    match read_ppm(&mut &output.stdout[..])? {
+        Some(image) => Ok(DynamicImage::ImageRgb8(image)),
+        None => Err(Error::new(ErrorKind::NotFound, "no frame at that time")),
+    }
+}
+
+fn ffmpeg() -> Command {
+    let mut command = Command::new("ffmpeg");
+    command
+        .args(["-nostdin", "-hide_banner", "-loglevel", "error"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    command
+}
+
+// Reads the next binary PPM of a stream, None when the stream ended cleanly before it
+fn read_ppm<R: BufRead>(reader: &mut R) -> Result<Option<RgbImage>, Error> {
+    if reader.fill_buf()?.is_empty() {
+        return Ok(None);
+    }
+    let invalid = |what: &str| Error::new(ErrorKind::InvalidData, format!("invalid PPM {}", what));
+    let mut fields = [0u32; 3];
+    if ppm_token(reader)? != "P6" {
+        return Err(invalid("magic"));
+    }
+    for field in fields.iter_mut() {
+        *field = ppm_token(reader)?.parse().map_err(|_| invalid("header"))?;
+    }
+    let [width, height, max] = fields;
+    if max != 255 {
+        return Err(invalid("depth"));
+    }
+    let mut pixels = vec![0; width as usize * height as usize * 3];
+    reader.read_exact(&mut pixels)?;
+    RgbImage::from_raw(width, height, pixels)
+        .map(Some)
+        .ok_or_else(|| invalid("size"))
+}
+
+// Header fields are separated by whitespace, the single byte after the last one included
+fn ppm_token<R: BufRead>(reader: &mut R) -> Result<String, Error> {
+    let mut token = String::new();
+    for byte in reader.bytes() {
+        let byte = byte?;
+        if byte.is_ascii_whitespace() {
+            if token.is_empty() {
+                continue;
+            }
+            return Ok(token);
+        }
+        token.push(byte as char);
+    }
+    Err(Error::new(ErrorKind::UnexpectedEof, "truncated PPM header"))
+}
+
+#[test]
+fn frame_paths() {
+    let path = frame_path(Path::new("films/intro.mp4"), 12.50001);
+    assert_eq!(path, Path::new("films/intro.mp4#t=12.5"));
+    assert_eq!(
+        split_frame(&path),
+        Some((Path::new("films/intro.mp4"), 12.5))
+    );
+    assert_eq!(split_frame(Path::new("films/intro.mp4")), None);
+    assert_eq!(split_frame(Path::new("notes/a.txt#t=3")), None);
+    assert_eq!(split_frame(Path::new("films/intro.mp4#t=soon")), None);
+}
+
+#[test]
+fn sample_frame_sequence() {
+    // Ten frames a second, a cut from bulbasaur to ivysaur after one second
+    let pokemon = |name: &str| dhash(&image::open(format!("./test/pokemon/{}.png", name)).unwrap());
+    let (before, after) = (pokemon("bulbasaur"), pokemon("ivysaur"));
+    let frames: Vec<(f64, IHash)> = (0..25)
+        .map(|i| (i as f64 / 10.0, if i < 10 { before } else { after }))
+        .collect();
+
+    let times = |kept: Vec<(f64, IHash)>| -> Vec<f64> { kept.into_iter().map(|k| k.0).collect() };
+    let every_second = keyframes(frames.clone(), Sampling::Interval(1.0));
+    assert_eq!(times(every_second), [0.0, 1.0, 2.0]);
+    let cuts = keyframes(frames.clone(), Sampling::SceneChange(4));
+    assert_eq!(cuts, [(0.0, before), (1.0, after)]);
+    assert_eq!(keyframes(frames, Sampling::SceneChange(64)).len(), 1);
+}
+
+#[test]
+fn reject_bad_intervals() {
+    for seconds in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+        let error = hash_video(Path::new("films/intro.mp4"), Sampling::Interval(seconds));
+        assert_eq!(error.unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+}
+
+#[test]
+fn read_ppm_stream() {
+    let mut stream = vec![];
+    for shade in [0u8, 200] {
+        stream.extend_from_slice(b"P6\n2 1\n255\n");
+        stream.extend_from_slice(&[shade; 6]);
+    }
+    let mut reader = &stream[..];
+    let first = read_ppm(&mut reader).unwrap().unwrap();
+    assert_eq!(first.dimensions(), (2, 1));
+    assert_eq!(first.get_pixel(1, 0).0, [0, 0, 0]);
+    assert_eq!(
+        read_ppm(&mut reader).unwrap().unwrap().get_pixel(0, 0).0,
+        [200; 3]
+    );
+    assert!(read_ppm(&mut reader).unwrap().is_none());
+    assert!(read_ppm(&mut &b"P5\n2 1\n255\n"[..]).is_err());
+    assert!(read_ppm(&mut &b"P6\n2 1\n255\n\0"[..]).is_err());
+}
+
+// Only runs where ffmpeg is installed
+#[test]
+fn hash_generated_clip() {
+    if Command::new("ffmpeg").arg("-version").output().is_err() {
+        return;
+    }
+    let clip = std::env::temp_dir().join("image-fingerprint-clip.avi");
+    let generated = ffmpeg()
+        .args([
+            "-y",
+            "-f",
+            "lavfi",
+            "-i",
+            "testsrc=duration=3:size=96x64:rate=10",
+        ])
+        .args(["-c:v", "mjpeg"])
+        .arg(&clip)
+        .status()
+        .unwrap();
+    assert!(generated.success());
+
+    let hashed = hash_video(&clip, Sampling::Interval(1.0)).unwrap();
+    let paths: Vec<PathBuf> = hashed.iter().map(|(_, path)| path.clone()).collect();
+    let expected: Vec<PathBuf> = [0.0, 1.0, 2.0]
+        .iter()
+        .map(|seconds| frame_path(&clip, *seconds))
+        .collect();
+    assert_eq!(paths, expected);
+    let frame = read_frame(&paths[1]).unwrap();
+    assert!(dhash(&frame).dist(&hashed[1].0) <= 4);
+    let _ = std::fs::remove_file(&clip);
+}
@@ -0,0 +1,75 @@
+//! JavaScript bindings for hashing in the browser, built with
+//! `cargo rustc --lib --release --target wasm32-unknown-unknown --crate-type cdylib` and then
+//! `wasm-bindgen --target web` on the resulting `lib.wasm`.
+//!
+//! Hashes are decimal strings, as JavaScript numbers cannot hold all 64 bits. They are the
+//! same strings the CLI prints and the server answers with.
+
+use image::{imageops::FilterType, DynamicImage};
+use wasm_bindgen::prelude::*;
+use web_sys::ImageData;
+
+use crate::ihash::{self, IHash, PixelFormat};
+
+fn to_image(image: &ImageData) -> Result<DynamicImage, JsError> {
+    from_rgba(&image.data(), image.width(), image.height())
+}
+
+fn from_rgba(pixels: &[u8], width: u32, height: u32) -> Result<DynamicImage, JsError> {
+    let stride = width as usize * PixelFormat::Rgba8.bytes_per_pixel();
+    ihash::image_from_raw(pixels, width, height, stride, PixelFormat::Rgba8)
+        .map_err(|_| JsError::new("image data does not match its size"))
+}
+
+fn rotation_hashes(image: &DynamicImage) -> Vec<String> {
+    let hashes = ihash::dhash_rotations(image, FilterType::Triangle);
+    hashes.into_iter().map(IHash::to_str).collect()
+}
+
+fn parse_hash(hash: &str) -> Result<IHash, JsError> {
+    hash.parse()
+        .map(IHash::new)
+        .map_err(|_| JsError::new(&format!("invalid hash: {}", hash)))
+}
+
+/// The hash stores are built from, of the pixels of a canvas.
+#[wasm_bindgen]
+pub fn dhash(image: &ImageData) -> Result<String, JsError> {
+    Ok(ihash::dhash(&to_image(image)?).to_str())
+}
+
+/// Hashes of the image turned clockwise by 0, 90, 180 and 270 degrees, for searching rotated
+/// copies.
+#[wasm_bindgen(js_name = dhashRotations)]
+pub fn dhash_rotations(image: &ImageData) -> Result<Vec<String>, JsError> {
+    Ok(rotation_hashes(&to_image(image)?))
+}
+
+/// Number of differing bits, against whichever of `hash2` or its mirror is closer.
+#[wasm_bindgen]
+pub fn distance(hash1: &str, hash2: &str) -> Result<u32, JsError> {
+    Ok(parse_hash(hash1)?.dist(&parse_hash(hash2)?))
+}
+
+// Run with `wasm-pack test --node`
+#[cfg(test)]
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[cfg(test)]
+#[wasm_bindgen_test]
+fn rotations_differ() {
+    let (width, height) = (32, 24);
+    let pixels: Vec<u8> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| ((x * 7 + y * 13 + x * y * 5) % 256) as u8))
+        .flat_map(|luma| [luma, luma, luma, 255])
+        .collect();
+    let image = from_rgba(&pixels, width, height).unwrap();
+    let hashes = rotation_hashes(&image);
+    assert_eq!(hashes.len(), 4);
+    assert!((1..4).all(|i| !hashes[..i].contains(&hashes[i])));
+
+    let stored = ihash::dhash(&image);
+    let rotated = rotation_hashes(&image.rotate90());
+    let closest = rotated.iter().map(|h| parse_hash(h).unwrap().dist(&stored)).min();
+    assert!(closest.unwrap() <= 2);
+}
@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::{Duration, Instant};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::animation::{self, FrameSampling};
+use crate::fgs::HashStore;
+use crate::pipeline::{hash_files_with, is_image, on_disk, HashPair};
+
+/// What a sync did to the store.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WatchSummary {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+}
+
+impl WatchSummary {
+    pub fn is_empty(&self) -> bool {
+        self.added == 0 && self.updated == 0 && self.removed == 0
+    }
+}
+
+/// Whether changes to the file matter to a store, images and the archives they may be inside.
+fn is_watched(path: &Path) -> bool {
+    #[cfg(feature = "archive")]
+    if crate::archive::is_archive(path) {
+        return true;
+    }
+    is_image(path)
+}
+
+/// Watches the source folders of a store for images that appear, change or go away.
+///
+/// Events are collected until a path has been quiet for the debounce time, so a file that is
+/// still being written is only hashed once it is done.
+pub struct StoreWatcher {
+    // Events stop once this is dropped
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    // Last time each path was touched
+    pending: HashMap<PathBuf, Instant>,
+    debounce: Duration,
+}
+
+impl StoreWatcher {
+    /// Watches the files directly inside each folder.
+    pub fn new<'a>(
+        dirs: impl IntoIterator<Item = &'a Path>,
+        debounce: Duration,
+    ) -> notify::Result<Self> {
+        let (tx, rx) = channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = tx.send(event);
+        })?;
+        for dir in dirs {
+            watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        }
+        Ok(StoreWatcher {
+            _watcher: watcher,
+            events: rx,
+            pending: HashMap::new(),
+            debounce,
+        })
+    }
+
+    /// Watches the folders recorded with [`HashStore::add_source`].
+    pub fn for_store(store: &HashStore, debounce: Duration) -> notify::Result<Self> {
+        Self::new(store.sources(), debounce)
+    }
+
+    /// Image and archive paths that changed and have been quiet for at least the debounce time.
+    ///
+    /// Each path is handed out once per burst of events.
+    pub fn settled(&mut self) -> Vec<PathBuf> {
+        let now = Instant::now();
+        for event in self.events.try_iter().filter_map(Result::ok) {
+            if matches!(event.kind, EventKind::Access(_)) {
+                continue;
+            }
+            for path in event.paths.into_iter().filter(|path| is_watched(path)) {
+                self.pending.insert(path, now);
+            }
+        }
+
+        let mut settled = vec![];
+        self.pending.retain(|path, last| {
+            let quiet = now.duration_since(*last) >= self.debounce;
+            if quiet {
+                settled.push(path.clone());
+            }
+            !quiet
+        });
+        settled.sort();
+        settled
+    }
+
+    /// Applies whatever settled since the last poll to the store.
+    pub fn poll(&mut self, store: &mut HashStore) -> WatchSummary {
+        let paths = self.settled();
+        sync_paths(store, &paths)
+    }
+}
+
+/// Positions of the entries stored for a file, the members of an archive and the frames of a
+/// video or animation included.
+pub fn entries_of(store: &HashStore, file: &Path) -> Vec<usize> {
+    store
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, path))| on_disk(path) == file)
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Brings the entries for `paths` in line with the files on disk.
+///
+/// Files are hashed again, archives member by member, and animations frame by frame if the
+/// store has their frames. Everything stored for a path that no longer exists is dropped. Files
+/// that cannot be decoded are left alone, they may still be getting written.
+pub fn sync_paths(store: &mut HashStore, paths: &[PathBuf]) -> WatchSummary {
+    let mut summary = WatchSummary::default();
+    for path in paths {
+        let stored = entries_of(store, path);
+        if !path.is_file() {
+            if !stored.is_empty() {
+                store.remove_entries(&stored);
+                summary.removed += 1;
+            }
+            continue;
+        }
+
+        let has_frames = store
+            .iter()
+            .any(|(_, p)| on_disk(p) == path && animation::split_frame(p).is_some());
+        let frames = if has_frames {
+            FrameSampling::All
+        } else {
+            FrameSampling::First
+        };
+        let hashed: Vec<HashPair> = hash_files_with(vec![path.clone()], frames).iter().collect();
+        if hashed.is_empty() {
+            continue;
+        }
+        let unchanged = hashed.len() == stored.len()
+            && store
+                .iter()
+                .filter(|(_, p)| on_disk(p) == path)
+                .all(|(h, p)| hashed.iter().any(|(hash, other)| hash == h && other == p));
+        if unchanged {
+            continue;
+        }
+        if stored.is_empty() {
+            summary.added += 1;
+        } else {
+            summary.updated += 1;
+        }
+        store.remove_entries(&stored);
+        for (hash, hashed_path) in hashed.iter() {
+            store.add_hash(hash, hashed_path);
+        }
+    }
+    summary
+}
+
+#[test]
+fn watcher_follows_changes() {
+    use std::fs;
+
+    fn wait_for(
+        watcher: &mut StoreWatcher,
+        store: &mut HashStore,
+        done: impl Fn(&WatchSummary) -> bool,
+    ) {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(10) {
+            if done(&watcher.poll(store)) {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        panic!("watcher did not pick up the change");
+    }
+
+    let dir = std::env::temp_dir().join("image-fingerprint-watch");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let mut store = HashStore::new();
+    store.add_source(&dir);
+    let mut watcher = StoreWatcher::for_store(&store, Duration::from_millis(50)).unwrap();
+
+    let image = dir.join("pokemon.png");
+    fs::copy("./test/pokemon/bulbasaur.png", &image).unwrap();
+    fs::write(dir.join("notes.txt"), "not an image").unwrap();
+    wait_for(&mut watcher, &mut store, |s| s.added == 1);
+    assert_eq!(store.len(), 1);
+
+    fs::copy("./test/pokemon/ivysaur.png", &image).unwrap();
+    wait_for(&mut watcher, &mut store, |s| s.updated == 1);
+    assert_eq!(store.len(), 1);
+
+    fs::remove_file(&image).unwrap();
+    wait_for(&mut watcher, &mut store, |s| s.removed == 1);
+    assert!(store.is_empty());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[cfg(feature = "archive")]
+#[test]
+fn sync_archive_members_and_frames() {
+    use std::fs;
+    use std::io::Write;
+
+    let dir = std::env::temp_dir().join("image-fingerprint-watch-archive");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let cbz = dir.join("issue1.cbz");
+    let archive = std::slice::from_ref(&cbz);
+    let write_pages = |pages: &[&str]| {
+        let mut writer = zip::ZipWriter::new(fs::File::create(&cbz).unwrap());
+        for (i, name) in pages.iter().enumerate() {
+            let page = fs::read(format!("./test/pokemon/{}.png", name)).unwrap();
+            writer
+                .start_file(format!("{}.png", i), Default::default())
+                .unwrap();
+            writer.write_all(&page).unwrap();
+        }
+        writer.finish().unwrap();
+    };
+
+    let mut store = HashStore::new();
+    write_pages(&["bulbasaur", "ivysaur"]);
+    let summary = sync_paths(&mut store, archive);
+    assert_eq!(summary.added, 1);
+    assert_eq!(store.len(), 2);
+    assert!(sync_paths(&mut store, archive).is_empty());
+
+    // A page taken out of the archive goes with it
+    write_pages(&["bulbasaur"]);
+    assert_eq!(sync_paths(&mut store, archive).updated, 1);
+    assert_eq!(store.len(), 1);
+
+    let gif = dir.join("wave.gif");
+    store.add_hash(&crate::ihash::IHash::new(1), animation::frame_path(&gif, 0));
+    store.add_hash(&crate::ihash::IHash::new(2), animation::frame_path(&gif, 1));
+    fs::remove_file(&cbz).unwrap();
+    let summary = sync_paths(&mut store, &[cbz.clone(), gif.clone()]);
+    assert_eq!(summary.removed, 2);
+    assert!(store.is_empty());
+
+    let _ = fs::remove_dir_all(&dir);
+}